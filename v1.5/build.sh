@@ -0,0 +1,75 @@
+#!/bin/bash
+
+# Build script for Flowsta Identity DNA v1.5
+
+set -e
+
+echo "Building Flowsta Identity DNA v1.5 (site registration, private memberships, signed exports)"
+
+# Check if Holochain CLI is installed
+if ! command -v hc &> /dev/null; then
+    echo "Error: Holochain CLI (hc) not found"
+    echo "Install with: cargo install holochain_cli --version 0.6.0"
+    exit 1
+fi
+
+# Create workdir if it doesn't exist
+mkdir -p workdir
+
+# Registration attestors trusted by the sites zome (the Auth API's key, uhCAk...,
+# comma-separated). Compiled into the coordinator, so changing them doesn't
+# change the DNA hash. Conductor test builds also trust the test attestor
+# (seed 0x2a * 32, see client/tests/conductor.rs): never release those.
+TEST_REGISTRATION_ATTESTOR="uhCAkGX9rI-FshTLGq8g4-s1ep4m-DHaykgM0A5v6iz02jWGVpDPG"
+if [ "${RUN_CONDUCTOR_TESTS:-0}" = "1" ]; then
+    echo "Warning: this build trusts the test registration attestor"
+    FLOWSTA_REGISTRATION_ATTESTORS="${FLOWSTA_REGISTRATION_ATTESTORS:+$FLOWSTA_REGISTRATION_ATTESTORS,}$TEST_REGISTRATION_ATTESTOR"
+elif [ -z "${FLOWSTA_REGISTRATION_ATTESTORS:-}" ]; then
+    echo "Warning: FLOWSTA_REGISTRATION_ATTESTORS is not set; no site registration will count"
+fi
+export FLOWSTA_REGISTRATION_ATTESTORS
+
+# Build all zomes
+echo "Building zomes..."
+# (the verifier, CLI and client crates are native, not zomes)
+RUSTFLAGS='--cfg getrandom_backend="custom"' CARGO_TARGET_DIR=target cargo build --release --target wasm32-unknown-unknown --workspace \
+    --exclude flowsta_identity_verifier --exclude flowsta_identity_cli --exclude flowsta_identity_client
+
+# Copy wasm files flat to workdir (manifest_version "0" expects flat paths)
+echo "Copying WASM files..."
+cp target/wasm32-unknown-unknown/release/users_integrity.wasm workdir/
+cp target/wasm32-unknown-unknown/release/users_coordinator.wasm workdir/
+cp target/wasm32-unknown-unknown/release/sites_integrity.wasm workdir/
+cp target/wasm32-unknown-unknown/release/sites_coordinator.wasm workdir/
+cp target/wasm32-unknown-unknown/release/agent_linking_integrity.wasm workdir/
+cp target/wasm32-unknown-unknown/release/agent_linking_coordinator.wasm workdir/
+
+# Pack the DNA
+echo "Packing DNA..."
+hc dna pack workdir
+
+# The hApp binds the installed v1.4 cell as the previous role (see happ.yaml),
+# which is matched by DNA hash
+if [ ! -f ../v1.4/workdir/flowsta_identity_v1_4.dna ]; then
+    echo "Building previous DNA (v1.4)..."
+    (cd ../v1.4 && ./build.sh)
+fi
+PREVIOUS_DNA_HASH=$(hc dna hash ../v1.4/workdir/flowsta_identity_v1_4.dna)
+sed "s/\${PREVIOUS_DNA_HASH}/$PREVIOUS_DNA_HASH/" happ.yaml > workdir/happ.yaml
+
+# Pack the hApp
+echo "Packing hApp..."
+hc app pack workdir
+
+# Conductor tests for the Rust client, against the hApp just packed.
+# Opt-in (RUN_CONDUCTOR_TESTS=1): they compile a whole conductor.
+if [ "${RUN_CONDUCTOR_TESTS:-0}" = "1" ]; then
+    echo "Running conductor tests..."
+    cargo test -p flowsta_identity_client -- --ignored
+fi
+
+echo "Build complete!"
+echo ""
+echo "Outputs:"
+echo "  - DNA: workdir/flowsta_identity_v1_5.dna"
+echo "  - hApp: workdir/flowsta_identity_v1_5_happ.happ"
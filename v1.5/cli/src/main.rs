@@ -0,0 +1,232 @@
+//! `flowsta-identity`: inspect and verify Flowsta identity data without a conductor.
+//!
+//! Built on `flowsta_identity_verifier`, so every check gives the same answer
+//! as zome validation. Mostly for debugging failed `create_direct_link` calls.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use ed25519_dalek::{Signer, SigningKey};
+use flowsta_identity_verifier::*;
+use hdi::prelude::{AgentPubKey, Signature};
+
+#[derive(Parser)]
+#[command(name = "flowsta-identity", about = "Inspect and verify Flowsta identity data")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode, print and verify an IdentityExport (signed or not) msgpack bundle
+    Export {
+        /// File with the msgpack bytes, or "-" for stdin
+        path: PathBuf,
+        /// The input is base64 text rather than raw bytes
+        #[arg(long)]
+        base64: bool,
+    },
+    /// Decode, print and verify an IsSamePersonEntry msgpack blob
+    Link {
+        /// File with the msgpack bytes, or "-" for stdin
+        path: PathBuf,
+        /// The input is base64 text rather than raw bytes
+        #[arg(long)]
+        base64: bool,
+    },
+    /// Check one agent's signature over the pair it forms with another
+    /// (what create_direct_link checks for `other_signature`)
+    VerifyLink {
+        /// Agent that made the signature
+        #[arg(long)]
+        signer: String,
+        /// The other agent in the pair
+        #[arg(long)]
+        other: String,
+        /// Base64 of the 64-byte signature
+        #[arg(long)]
+        signature: String,
+    },
+    /// Print sorted_agent_pair_bytes for two agent keys, and the msgpack bytes actually signed
+    PairBytes { agent_a: String, agent_b: String },
+    /// Sign the pair with a key derived from a local seed, as a linking client would. For testing only.
+    SignTest {
+        /// 32-byte Ed25519 seed, hex
+        #[arg(long)]
+        seed: String,
+        /// The other agent in the pair
+        #[arg(long)]
+        other: String,
+    },
+}
+
+/// Parse an agent key: Holochain's `uhCAk...` form or standard base64 of the 39 bytes
+fn parse_agent(key: &str) -> Result<AgentPubKey, String> {
+    let bytes = match key.strip_prefix('u') {
+        // Multibase 'u': base64url without padding
+        Some(encoded) => URL_SAFE_NO_PAD.decode(encoded),
+        None => STANDARD.decode(key),
+    }
+    .map_err(|e| format!("{}: {}", key, e))?;
+    agent_from_raw_39(&bytes).map_err(|e| format!("{}: {}", key, e))
+}
+
+fn parse_signature(signature: &str) -> Result<Signature, String> {
+    let bytes = STANDARD
+        .decode(signature)
+        .map_err(|e| format!("signature: {}", e))?;
+    let bytes: [u8; 64] = bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("signature: expected 64 bytes, got {}", bytes.len()))?;
+    Ok(Signature(bytes))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.is_ascii() || hex.len() % 2 != 0 {
+        return Err("hex must be an even number of hex digits".to_string());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| format!("hex: {}", e)))
+        .collect()
+}
+
+/// Read a file (or stdin for "-"), decoding base64 text if asked
+fn read_input(path: &Path, base64: bool) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    if path.as_os_str() == "-" {
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("stdin: {}", e))?;
+    } else {
+        bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    if !base64 {
+        return Ok(bytes);
+    }
+    let text = String::from_utf8(bytes).map_err(|_| "base64 input is not text".to_string())?;
+    STANDARD
+        .decode(text.trim())
+        .map_err(|e| format!("base64: {}", e))
+}
+
+/// Print a check's outcome; returns whether it passed
+fn report(what: &str, result: Result<(), VerifyError>) -> bool {
+    match result {
+        Ok(()) => {
+            println!("{}: OK", what);
+            true
+        }
+        Err(e) => {
+            println!("{}: FAILED ({})", what, e);
+            false
+        }
+    }
+}
+
+fn report_links(links: &[ExportedAgentLink]) -> bool {
+    let mut ok = true;
+    for link in links {
+        ok &= report(
+            &format!("agent link {}", link.action_hash),
+            verify_exported_link(link),
+        );
+    }
+    ok
+}
+
+fn run(command: Command) -> Result<bool, String> {
+    match command {
+        Command::Export { path, base64 } => {
+            let bytes = read_input(&path, base64)?;
+            if let Ok(signed) = holochain_serialized_bytes::decode::<_, SignedIdentityExport>(&bytes) {
+                let export: IdentityExport =
+                    holochain_serialized_bytes::decode(&signed.export_bytes)
+                        .map_err(|e| format!("Export bytes are not an IdentityExport: {}", e))?;
+                println!("{:#?}", export);
+                println!("signature: {:?}", signed.signature);
+                let verified = verify_signed_export(&signed).map(|_| ());
+                let signature_ok = report("export signature", verified);
+                return Ok(report_links(&export.agent_links) && signature_ok);
+            }
+            let export: IdentityExport = holochain_serialized_bytes::decode(&bytes)
+                .map_err(|e| format!("Not a (Signed)IdentityExport: {}", e))?;
+            println!("{:#?}", export);
+            println!("export signature: none (unsigned export)");
+            Ok(report_links(&export.agent_links))
+        }
+        Command::Link { path, base64 } => {
+            let bytes = read_input(&path, base64)?;
+            let entry: IsSamePersonEntry = holochain_serialized_bytes::decode(&bytes)
+                .map_err(|e| format!("Not an IsSamePersonEntry: {}", e))?;
+            println!("{:#?}", entry);
+            Ok(report("IsSamePersonEntry", verify_is_same_person(&entry)))
+        }
+        Command::VerifyLink {
+            signer,
+            other,
+            signature,
+        } => {
+            let signer = parse_agent(&signer)?;
+            let other = parse_agent(&other)?;
+            let signature = parse_signature(&signature)?;
+            let result = match verify_link_signature(&signer, &other, &signature) {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(VerifyError::BadSignature(format!(
+                    "does not verify against {} over the sorted pair",
+                    signer
+                ))),
+                Err(e) => Err(e),
+            };
+            Ok(report("link signature", result))
+        }
+        Command::PairBytes { agent_a, agent_b } => {
+            let agent_a = parse_agent(&agent_a)?;
+            let agent_b = parse_agent(&agent_b)?;
+            let payload = sorted_agent_pair_bytes(&agent_a, &agent_b).map_err(|e| e.to_string())?;
+            let signed = holochain_serialized_bytes::encode(&payload).map_err(|e| e.to_string())?;
+            println!("sorted_agent_pair_bytes ({} bytes): {}", payload.len(), to_hex(&payload));
+            println!("signed msgpack bytes ({} bytes): {}", signed.len(), to_hex(&signed));
+            Ok(true)
+        }
+        Command::SignTest { seed, other } => {
+            let seed: [u8; 32] = from_hex(&seed)?
+                .try_into()
+                .map_err(|_| "seed must be 32 bytes".to_string())?;
+            let signing_key = SigningKey::from_bytes(&seed);
+            let agent = agent_from_ed25519(&signing_key.verifying_key().to_bytes());
+            let other = parse_agent(&other)?;
+
+            // Sign exactly what the zome `sign` function would: the msgpack-encoded pair
+            let payload = sorted_agent_pair_bytes(&agent, &other).map_err(|e| e.to_string())?;
+            let signed = holochain_serialized_bytes::encode(&payload).map_err(|e| e.to_string())?;
+            let signature = signing_key.sign(&signed);
+
+            println!("agent: {}", agent);
+            println!("agent (base64): {}", STANDARD.encode(agent.get_raw_39()));
+            println!("signature (base64): {}", STANDARD.encode(signature.to_bytes()));
+            Ok(true)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
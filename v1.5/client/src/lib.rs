@@ -0,0 +1,194 @@
+//! Typed client for the Flowsta Identity DNA's zome API.
+//!
+//! Wraps a connected `AppWebsocket` so consumers call `register_user`,
+//! `join_site`, `create_direct_link`, ... with the DNA's own types instead
+//! of re-declaring payloads and zome/function names by hand.
+//!
+//! Entry types, zome call inputs and signals come from the integrity
+//! crates (and profile schemas from users_profile), the same definitions
+//! the coordinator zomes use.
+
+use std::fmt;
+
+use hdi::prelude::*;
+use holochain_client::{AppWebsocket, ConductorApiError, ZomeCallTarget};
+use serde::de::DeserializeOwned;
+
+pub use agent_linking_integrity::{AgentLinkingSignal, AgentPair, DirectLinkInput};
+pub use identity_errors::IdentityError;
+pub use sites_integrity::{JoinSiteInput, JoinSiteOptions, SitesSignal};
+pub use users_integrity::UserProfile;
+pub use users_profile::{LatestUserProfile, UserProfileV1, UsersSignal};
+
+/// Role name of the identity DNA in the v1.5 hApp
+pub const DEFAULT_ROLE_NAME: &str = "flowsta_identity_v1_5";
+
+pub const USERS_ZOME: &str = "users";
+pub const SITES_ZOME: &str = "sites";
+pub const AGENT_LINKING_ZOME: &str = "agent_linking";
+
+// ── Signals ─────────────────────────────────────────────────────────
+
+/// Any signal emitted by the users, sites or agent_linking zomes.
+/// Decode an app signal's payload (its `ExternIO`) as this type; each
+/// zome's signals are its integrity crate's own enum, told apart by `type`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum IdentitySignal {
+    Users(UsersSignal),
+    Sites(SitesSignal),
+    /// Also received from the other agent in the link
+    AgentLinking(AgentLinkingSignal),
+}
+
+// ── Client ──────────────────────────────────────────────────────────
+
+/// Why a call failed
+#[derive(Debug)]
+pub enum ClientError {
+    /// The conductor rejected or failed the call (including zome errors)
+    Conductor(ConductorApiError),
+    /// The payload or the response didn't match the expected type
+    Serialization(SerializedBytesError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Conductor(e) => write!(f, "Zome call failed: {:?}", e),
+            ClientError::Serialization(e) => write!(f, "Serialization failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl ClientError {
+    /// The zome's IdentityError, when the call failed with one; branch on its `code()`
+    pub fn identity_error(&self) -> Option<IdentityError> {
+        match self {
+            // The conductor's error text embeds the guest string, see `IdentityError::parse`
+            ClientError::Conductor(e) => IdentityError::parse(&format!("{:?}", e)),
+            ClientError::Serialization(_) => None,
+        }
+    }
+}
+
+impl From<ConductorApiError> for ClientError {
+    fn from(e: ConductorApiError) -> Self {
+        ClientError::Conductor(e)
+    }
+}
+
+impl From<SerializedBytesError> for ClientError {
+    fn from(e: SerializedBytesError) -> Self {
+        ClientError::Serialization(e)
+    }
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// Typed calls into one identity DNA cell
+pub struct IdentityClient {
+    app_ws: AppWebsocket,
+    role_name: String,
+}
+
+impl IdentityClient {
+    /// Client for the default v1.5 role. The websocket must already be
+    /// authenticated and able to sign zome calls for the cell.
+    pub fn new(app_ws: AppWebsocket) -> Self {
+        Self::with_role(app_ws, DEFAULT_ROLE_NAME)
+    }
+
+    /// Client for the identity DNA installed under another role name
+    pub fn with_role(app_ws: AppWebsocket, role_name: impl Into<String>) -> Self {
+        Self {
+            app_ws,
+            role_name: role_name.into(),
+        }
+    }
+
+    /// The underlying websocket, for calls this client doesn't wrap
+    pub fn app_ws(&self) -> &AppWebsocket {
+        &self.app_ws
+    }
+
+    /// Call any zome function on the identity cell
+    pub async fn call<I, O>(&self, zome: &str, function: &str, payload: I) -> ClientResult<O>
+    where
+        I: Serialize + fmt::Debug,
+        O: DeserializeOwned + fmt::Debug,
+    {
+        let response = self
+            .app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName(self.role_name.clone()),
+                zome.into(),
+                function.into(),
+                ExternIO::encode(payload)?,
+            )
+            .await?;
+        Ok(response.decode()?)
+    }
+
+    // ── users ──
+
+    pub async fn register_user(&self, profile: LatestUserProfile) -> ClientResult<Record> {
+        self.call(USERS_ZOME, "register_user", profile).await
+    }
+
+    pub async fn update_profile(&self, profile: LatestUserProfile) -> ClientResult<Record> {
+        self.call(USERS_ZOME, "update_profile", profile).await
+    }
+
+    pub async fn get_my_profile(&self) -> ClientResult<Option<Record>> {
+        self.call(USERS_ZOME, "get_my_profile", ()).await
+    }
+
+    pub async fn get_profile(&self, agent: AgentPubKey) -> ClientResult<Option<Record>> {
+        self.call(USERS_ZOME, "get_profile", agent).await
+    }
+
+    /// An agent's profile normalized to the latest schema
+    pub async fn get_latest_profile(
+        &self,
+        agent: AgentPubKey,
+    ) -> ClientResult<Option<LatestUserProfile>> {
+        self.call(USERS_ZOME, "get_latest_profile", agent).await
+    }
+
+    // ── sites ──
+
+    /// Join a site by ID (`"example.com"`) or with `JoinSiteOptions`
+    pub async fn join_site(&self, input: impl Into<JoinSiteInput>) -> ClientResult<Record> {
+        self.call(SITES_ZOME, "join_site", input.into()).await
+    }
+
+    pub async fn get_my_sites(&self) -> ClientResult<Vec<Record>> {
+        self.call(SITES_ZOME, "get_my_sites", ()).await
+    }
+
+    /// Leave a site by deleting one of the caller's memberships
+    pub async fn leave_site(&self, membership_hash: ActionHash) -> ClientResult<ActionHash> {
+        self.call(SITES_ZOME, "leave_site", membership_hash).await
+    }
+
+    // ── agent_linking ──
+
+    pub async fn create_direct_link(&self, input: DirectLinkInput) -> ClientResult<ActionHash> {
+        self.call(AGENT_LINKING_ZOME, "create_direct_link", input).await
+    }
+
+    pub async fn get_linked_agents(&self, agent: AgentPubKey) -> ClientResult<Vec<AgentPubKey>> {
+        self.call(AGENT_LINKING_ZOME, "get_linked_agents", agent).await
+    }
+
+    pub async fn are_agents_linked(&self, agents: AgentPair) -> ClientResult<bool> {
+        self.call(AGENT_LINKING_ZOME, "are_agents_linked", agents).await
+    }
+
+    pub async fn revoke_link(&self, entry_action_hash: ActionHash) -> ClientResult<ActionHash> {
+        self.call(AGENT_LINKING_ZOME, "revoke_link", entry_action_hash).await
+    }
+}
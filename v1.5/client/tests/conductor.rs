@@ -0,0 +1,845 @@
+//! Runs the client against a locally spawned conductor with the hApp built
+//! by build.sh (`RUN_CONDUCTOR_TESTS=1 ./build.sh` runs these after packing
+//! it). To run them on their own: `cargo test -p flowsta_identity_client -- --ignored`,
+//! against a hApp built that way (it trusts the test registration attestor).
+
+use std::fmt;
+use std::future::Future;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ed25519_dalek::{Signer, SigningKey};
+use flowsta_identity_client::*;
+use flowsta_identity_verifier::{agent_from_ed25519, sorted_agent_pair_bytes, verify_pairwise_proof};
+use hdi::prelude::*;
+use holochain::prelude::{
+    AppBundleSource, CellInfo, InstallAppPayload, RoleSettings, RoleSettingsMap,
+};
+use holochain::sweettest::SweetConductor;
+use holochain_client::{
+    AdminWebsocket, AllowedOrigins, AppWebsocket, AuthorizeSigningCredentialsPayload,
+    ClientAgentSigner,
+};
+use sites_integrity::{
+    registration_attestation_payload_bytes, OwnerSignature, PairwiseProof, RegistrationAttestation,
+    SiteConsent, SiteOwners, SiteOwnershipTransfer, MAX_MEMBERSHIP_METADATA_BYTES,
+};
+
+const APP_ID: &str = "flowsta-identity-client-test";
+const PREVIOUS_APP_ID: &str = "flowsta-identity-client-test-v1-4";
+const PREVIOUS_ROLE_NAME: &str = "flowsta_identity_v1_4";
+
+/// Seed of the registration attestor that build.sh adds to the trusted set
+/// when RUN_CONDUCTOR_TESTS=1 (`TEST_REGISTRATION_ATTESTOR`)
+const TEST_ATTESTOR_SEED: [u8; 32] = [0x2a; 32];
+
+/// The sites zome's register_site input
+#[derive(Serialize, Debug)]
+struct RegisterSite {
+    site_id: String,
+    invite_only: bool,
+    membership_ttl: Option<i64>,
+    attestation: RegistrationAttestation,
+}
+
+/// The sites zome's create_invite input
+#[derive(Serialize, Debug)]
+struct CreateInvite {
+    site_id: String,
+    invitee: Option<AgentPubKey>,
+    code: Option<String>,
+    max_uses: u32,
+    expires_at: Option<i64>,
+    membership_ttl: Option<i64>,
+}
+
+/// The sites zome's join_site_with_invite input
+#[derive(Serialize, Debug)]
+struct JoinWithInvite {
+    invite_hash: ActionHash,
+    code: Option<String>,
+}
+
+/// The sites zome's prepare_ownership_transfer input
+#[derive(Serialize, Debug)]
+struct TransferOwnership {
+    site_id: String,
+    owners: Vec<AgentPubKey>,
+    threshold: u32,
+}
+
+/// The sites zome's grant_consent input
+#[derive(Serialize, Debug)]
+struct GrantConsent {
+    site_id: String,
+    scopes: Vec<String>,
+    expires_at: Option<i64>,
+}
+
+/// The sites zome's renew_membership input
+#[derive(Serialize, Debug)]
+struct RenewMembership {
+    membership_hash: ActionHash,
+    expires_at: i64,
+}
+
+/// The sites zome's set_membership_metadata input
+#[derive(Serialize, Debug)]
+struct SetMetadata {
+    membership_hash: ActionHash,
+    version: u32,
+    data: String,
+}
+
+/// The sites zome's is_agent_site_member input
+#[derive(Serialize, Debug)]
+struct AgentSiteQuery {
+    agent: AgentPubKey,
+    site_id: String,
+    blinding_key: Option<Vec<u8>>,
+}
+
+/// The parts of get_consents' output these tests read
+#[derive(Deserialize, Debug)]
+struct Consent {
+    consent: SiteConsent,
+    active: bool,
+}
+
+/// The parts of get_membership_validity's output these tests read
+#[derive(Deserialize, Debug)]
+struct MembershipValidity {
+    valid_until: Option<i64>,
+    active: bool,
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// An attestation signed by `attestor_key` that `owner` controls `site_id`
+fn attest(attestor_key: &SigningKey, site_id: &str, owner: &AgentPubKey) -> RegistrationAttestation {
+    let attested_at = now();
+    let payload = registration_attestation_payload_bytes(site_id, owner, attested_at);
+    RegistrationAttestation {
+        attestor: agent_from_ed25519(&attestor_key.verifying_key().to_bytes()),
+        attested_at,
+        signature: Signature(attestor_key.sign(&payload).to_bytes()),
+    }
+}
+
+fn register_site_input(site_id: &str, attestation: RegistrationAttestation) -> RegisterSite {
+    RegisterSite {
+        site_id: site_id.to_string(),
+        invite_only: false,
+        membership_ttl: None,
+        attestation,
+    }
+}
+
+/// Spawn a fresh conductor with one agent installed (see `install_agent`)
+async fn connect() -> (SweetConductor, IdentityClient, AgentPubKey) {
+    let conductor = SweetConductor::from_standard_config().await;
+    let (client, agent) = install_agent(&conductor, "").await;
+    (conductor, client, agent)
+}
+
+/// Install the v1.4 hApp and then the v1.5 hApp bound to its cell (the
+/// previous role uses the existing cell) for a new agent, and connect a
+/// signing app websocket. `suffix` keeps each agent's app IDs apart.
+async fn install_agent(conductor: &SweetConductor, suffix: &str) -> (IdentityClient, AgentPubKey) {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let happ = manifest_dir.join("../workdir/flowsta_identity_v1_5_happ.happ");
+    let previous_happ = manifest_dir.join("../../v1.4/workdir/flowsta_identity_v1_4_happ.happ");
+    for path in [&happ, &previous_happ] {
+        assert!(path.exists(), "{} not found: run build.sh first", path.display());
+    }
+    let app_id = format!("{}{}", APP_ID, suffix);
+    let previous_app_id = format!("{}{}", PREVIOUS_APP_ID, suffix);
+
+    let admin_port = conductor.get_arbitrary_admin_websocket_port().unwrap();
+    let admin_ws = AdminWebsocket::connect((Ipv4Addr::LOCALHOST, admin_port), None)
+        .await
+        .unwrap();
+
+    let previous_app = admin_ws
+        .install_app(InstallAppPayload {
+            source: AppBundleSource::Path(previous_happ),
+            agent_key: None,
+            installed_app_id: Some(previous_app_id.clone()),
+            network_seed: None,
+            roles_settings: None,
+            ignore_genesis_failure: false,
+        })
+        .await
+        .unwrap();
+    let previous_cell_id = match &previous_app.cell_info[PREVIOUS_ROLE_NAME][0] {
+        CellInfo::Provisioned(cell) => cell.cell_id.clone(),
+        _ => panic!("Expected a provisioned v1.4 cell"),
+    };
+    admin_ws.enable_app(previous_app_id).await.unwrap();
+
+    admin_ws
+        .install_app(InstallAppPayload {
+            source: AppBundleSource::Path(happ),
+            agent_key: Some(previous_app.agent_pub_key.clone()),
+            installed_app_id: Some(app_id.clone()),
+            network_seed: None,
+            roles_settings: Some(RoleSettingsMap::from([(
+                PREVIOUS_ROLE_NAME.to_string(),
+                RoleSettings::UseExisting {
+                    cell_id: previous_cell_id,
+                },
+            )])),
+            ignore_genesis_failure: false,
+        })
+        .await
+        .unwrap();
+    admin_ws.enable_app(app_id.clone()).await.unwrap();
+
+    let app_port = admin_ws
+        .attach_app_interface(0, AllowedOrigins::Any, None)
+        .await
+        .unwrap();
+    let token = admin_ws
+        .issue_app_auth_token(app_id.into())
+        .await
+        .unwrap()
+        .token;
+    let signer = ClientAgentSigner::default();
+    let app_ws = AppWebsocket::connect(
+        (Ipv4Addr::LOCALHOST, app_port),
+        token,
+        signer.clone().into(),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let app_info = app_ws.app_info().await.unwrap().unwrap();
+    let cell_id = match &app_info.cell_info[DEFAULT_ROLE_NAME][0] {
+        CellInfo::Provisioned(cell) => cell.cell_id.clone(),
+        _ => panic!("Expected a provisioned identity cell"),
+    };
+    let credentials = admin_ws
+        .authorize_signing_credentials(AuthorizeSigningCredentialsPayload {
+            cell_id: cell_id.clone(),
+            functions: None,
+        })
+        .await
+        .unwrap();
+    signer.add_credentials(cell_id, credentials);
+
+    (IdentityClient::new(app_ws), app_info.agent_pub_key)
+}
+
+/// Call `read` until `done` accepts its result. Other agents' records
+/// take a moment to reach this agent's cell.
+async fn eventually<T, F, Fut>(mut read: F, done: impl Fn(&T) -> bool) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = T>,
+{
+    for _ in 0..60 {
+        let result = read().await;
+        if done(&result) {
+            return result;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    panic!("Timed out waiting for another agent's records");
+}
+
+/// Whether a call failed only because a record hasn't reached this cell yet
+fn is_not_found<T>(result: &ClientResult<T>) -> bool {
+    matches!(
+        result,
+        Err(e) if e.identity_error().is_some_and(|e| e.code() == "NOT_FOUND")
+    )
+}
+
+/// The IdentityError a call failed with
+fn rejection<T: fmt::Debug>(result: ClientResult<T>) -> IdentityError {
+    result.unwrap_err().identity_error().unwrap()
+}
+
+/// Wait until `client`'s cell sees example.com's registration
+async fn wait_for_registration(client: &IdentityClient) {
+    eventually(
+        || client.call::<_, Option<SiteOwners>>(SITES_ZOME, "get_site_owners", "example.com".to_string()),
+        |owners| matches!(owners, Ok(Some(_))),
+    )
+    .await;
+}
+
+/// Register example.com to `owner` through the test attestor
+async fn register_example(
+    client: &IdentityClient,
+    owner: &AgentPubKey,
+    invite_only: bool,
+    membership_ttl: Option<i64>,
+) -> Record {
+    let attestor_key = SigningKey::from_bytes(&TEST_ATTESTOR_SEED);
+    let input = RegisterSite {
+        invite_only,
+        membership_ttl,
+        ..register_site_input("example.com", attest(&attestor_key, "example.com", owner))
+    };
+    client.call(SITES_ZOME, "register_site", input).await.unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from build.sh"]
+async fn profile_site_and_link_round_trip() {
+    let (_conductor, client, me) = connect().await;
+
+    // Profile
+    let profile = LatestUserProfile {
+        did: "did:flowsta:client-test".to_string(),
+        created_at: 1_700_000_000_000,
+        updated_at: 1_700_000_000_000,
+    };
+    client.register_user(profile).await.unwrap();
+
+    let stored = client.get_profile(me.clone()).await.unwrap();
+    assert!(stored.is_some());
+    let latest = client.get_latest_profile(me.clone()).await.unwrap().unwrap();
+    assert_eq!(latest.did, "did:flowsta:client-test");
+
+    // Site membership (canonicalized by the zome)
+    client.join_site("https://Example.com/").await.unwrap();
+    assert_eq!(client.get_my_sites().await.unwrap().len(), 1);
+
+    // Direct link with a local "desktop" key, signed the way the zome `sign` does
+    let desktop_key = SigningKey::from_bytes(&[7u8; 32]);
+    let desktop = agent_from_ed25519(&desktop_key.verifying_key().to_bytes());
+    let payload = sorted_agent_pair_bytes(&me, &desktop).unwrap();
+    let signature = desktop_key.sign(&holochain_serialized_bytes::encode(&payload).unwrap());
+
+    client
+        .create_direct_link(DirectLinkInput {
+            other_agent: desktop.clone(),
+            other_signature: Signature(signature.to_bytes()),
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(client.get_linked_agents(me.clone()).await.unwrap(), vec![desktop.clone()]);
+    assert!(client
+        .are_agents_linked(AgentPair {
+            agent_a: me,
+            agent_b: desktop,
+        })
+        .await
+        .unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from build.sh"]
+async fn create_direct_link_rejects_a_bad_signature() {
+    let (_conductor, client, _me) = connect().await;
+
+    let desktop_key = SigningKey::from_bytes(&[9u8; 32]);
+    let desktop = agent_from_ed25519(&desktop_key.verifying_key().to_bytes());
+
+    let result = client
+        .create_direct_link(DirectLinkInput {
+            other_agent: desktop,
+            other_signature: Signature([0u8; 64]),
+        })
+        .await;
+    let error = result.unwrap_err().identity_error().unwrap();
+    assert_eq!(error.code(), "INVALID_SIGNATURE");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from RUN_CONDUCTOR_TESTS=1 ./build.sh"]
+async fn registration_with_a_trusted_attestor_counts() {
+    let (_conductor, client, me) = connect().await;
+    let attestor_key = SigningKey::from_bytes(&TEST_ATTESTOR_SEED);
+
+    let record: Record = client
+        .call(
+            SITES_ZOME,
+            "register_site",
+            register_site_input("example.com", attest(&attestor_key, "example.com", &me)),
+        )
+        .await
+        .unwrap();
+
+    let current: Option<Record> = client
+        .call(SITES_ZOME, "get_site_registration", "example.com".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        current.map(|current| current.action_address().clone()),
+        Some(record.action_address().clone())
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from RUN_CONDUCTOR_TESTS=1 ./build.sh"]
+async fn registration_rejects_a_forged_or_untrusted_attestation() {
+    let (_conductor, client, me) = connect().await;
+    let attestor_key = SigningKey::from_bytes(&TEST_ATTESTOR_SEED);
+
+    // Signed for another site: fails validation
+    let forged = attest(&attestor_key, "other.example", &me);
+    let result: ClientResult<Record> = client
+        .call(SITES_ZOME, "register_site", register_site_input("example.com", forged))
+        .await;
+    assert!(result.is_err());
+
+    // Validly signed by a key nobody trusts
+    let stranger = SigningKey::from_bytes(&[0x2b; 32]);
+    let result: ClientResult<Record> = client
+        .call(
+            SITES_ZOME,
+            "register_site",
+            register_site_input("example.com", attest(&stranger, "example.com", &me)),
+        )
+        .await;
+    let error = result.unwrap_err().identity_error().unwrap();
+    assert_eq!(error.code(), "NOT_AUTHORIZED");
+
+    let current: Option<Record> = client
+        .call(SITES_ZOME, "get_site_registration", "example.com".to_string())
+        .await
+        .unwrap();
+    assert!(current.is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from build.sh"]
+async fn consent_accepts_only_known_unique_scopes() {
+    let (_conductor, client, _me) = connect().await;
+    let grant = |scopes: &[&str]| GrantConsent {
+        site_id: "example.com".to_string(),
+        scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        expires_at: None,
+    };
+
+    let _: ActionHash = client
+        .call(SITES_ZOME, "grant_consent", grant(&["did", "membership"]))
+        .await
+        .unwrap();
+
+    // Unknown, repeated or no scopes fail validation
+    let rejected: [&[&str]; 3] = [&["email"], &["did", "did"], &[]];
+    for scopes in rejected {
+        let result: ClientResult<ActionHash> =
+            client.call(SITES_ZOME, "grant_consent", grant(scopes)).await;
+        assert!(result.is_err(), "{:?} should be rejected", scopes);
+    }
+
+    let consents: Vec<Consent> = client
+        .call(SITES_ZOME, "get_consents", "example.com".to_string())
+        .await
+        .unwrap();
+    assert_eq!(consents.len(), 1);
+    assert_eq!(consents[0].consent.scopes, vec!["did", "membership"]);
+    assert!(consents[0].active);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from build.sh"]
+async fn pairwise_proofs_verify_only_as_revealed() {
+    let (_conductor, client, me) = connect().await;
+    client
+        .join_site(JoinSiteOptions {
+            site_id: "example.com".to_string(),
+            pairwise: true,
+            blinding_key: None,
+        })
+        .await
+        .unwrap();
+
+    let proof: PairwiseProof = client
+        .call(SITES_ZOME, "reveal_pairwise_proof", "example.com".to_string())
+        .await
+        .unwrap();
+    assert_eq!(proof.agent, me);
+    assert!(verify_pairwise_proof(&proof).is_ok());
+    let verified: bool = client
+        .call(SITES_ZOME, "verify_pairwise_proof", proof.clone())
+        .await
+        .unwrap();
+    assert!(verified);
+
+    // Replayed for another (or a non-canonical) site, claimed by another
+    // agent, or without the pairwise key's own signature
+    let stranger = SigningKey::from_bytes(&[7u8; 32]);
+    let forgeries = [
+        PairwiseProof {
+            site_id: "other.example".to_string(),
+            ..proof.clone()
+        },
+        PairwiseProof {
+            site_id: "Example.com".to_string(),
+            ..proof.clone()
+        },
+        PairwiseProof {
+            agent: agent_from_ed25519(&stranger.verifying_key().to_bytes()),
+            ..proof.clone()
+        },
+        PairwiseProof {
+            key_proof: proof.proof.clone(),
+            ..proof.clone()
+        },
+    ];
+    for forged in forgeries {
+        let verified: bool = client
+            .call(SITES_ZOME, "verify_pairwise_proof", forged.clone())
+            .await
+            .unwrap();
+        assert!(!verified, "{:?} should not verify", forged);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from RUN_CONDUCTOR_TESTS=1 ./build.sh"]
+async fn invite_codes_admit_one_member_per_use() {
+    let (conductor, owner_client, owner) = connect().await;
+    let (member_client, member) = install_agent(&conductor, "-member").await;
+    register_example(&owner_client, &owner, true, None).await;
+
+    let invite_hash: ActionHash = owner_client
+        .call(
+            SITES_ZOME,
+            "create_invite",
+            CreateInvite {
+                site_id: "example.com".to_string(),
+                invitee: None,
+                code: Some("open-sesame".to_string()),
+                max_uses: 1,
+                expires_at: None,
+                membership_ttl: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    // Only site owners create invites
+    wait_for_registration(&member_client).await;
+    let result: ClientResult<ActionHash> = member_client
+        .call(
+            SITES_ZOME,
+            "create_invite",
+            CreateInvite {
+                site_id: "example.com".to_string(),
+                invitee: None,
+                code: Some("open-sesame".to_string()),
+                max_uses: 1,
+                expires_at: None,
+                membership_ttl: None,
+            },
+        )
+        .await;
+    assert_eq!(rejection(result).code(), "NOT_AUTHORIZED");
+
+    // The site is invite-only
+    let error = rejection(member_client.join_site("example.com").await);
+    assert_eq!(error.code(), "INVITE_REJECTED");
+
+    let redeem = |code: &str| JoinWithInvite {
+        invite_hash: invite_hash.clone(),
+        code: Some(code.to_string()),
+    };
+    let result = eventually(
+        || member_client.call::<_, Record>(SITES_ZOME, "join_site_with_invite", redeem("guess")),
+        |result| !is_not_found(result),
+    )
+    .await;
+    let error = rejection(result);
+    assert_eq!(error.code(), "INVITE_REJECTED");
+    assert!(error.message().contains("wrong"), "{}", error.message());
+
+    let _: Record = member_client
+        .call(SITES_ZOME, "join_site_with_invite", redeem("open-sesame"))
+        .await
+        .unwrap();
+
+    // Once the owner sees that redemption, the invite's one use is taken
+    eventually(
+        || {
+            owner_client.call::<_, bool>(
+                SITES_ZOME,
+                "is_agent_site_member",
+                AgentSiteQuery {
+                    agent: member.clone(),
+                    site_id: "example.com".to_string(),
+                    blinding_key: None,
+                },
+            )
+        },
+        |result| matches!(result, Ok(true)),
+    )
+    .await;
+    let error = rejection(
+        owner_client
+            .call::<_, Record>(SITES_ZOME, "join_site_with_invite", redeem("open-sesame"))
+            .await,
+    );
+    assert_eq!(error.code(), "INVITE_REJECTED");
+    assert!(error.message().contains("no uses left"), "{}", error.message());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from RUN_CONDUCTOR_TESTS=1 ./build.sh"]
+async fn addressed_invites_admit_only_the_invitee() {
+    let (conductor, owner_client, owner) = connect().await;
+    let (member_client, member) = install_agent(&conductor, "-member").await;
+    register_example(&owner_client, &owner, true, None).await;
+
+    let invite_hash: ActionHash = owner_client
+        .call(
+            SITES_ZOME,
+            "create_invite",
+            CreateInvite {
+                site_id: "example.com".to_string(),
+                invitee: Some(member.clone()),
+                code: None,
+                max_uses: 1,
+                expires_at: None,
+                membership_ttl: None,
+            },
+        )
+        .await
+        .unwrap();
+    let redeem = || JoinWithInvite {
+        invite_hash: invite_hash.clone(),
+        code: None,
+    };
+
+    let error = rejection(
+        owner_client
+            .call::<_, Record>(SITES_ZOME, "join_site_with_invite", redeem())
+            .await,
+    );
+    assert_eq!(error.code(), "INVITE_REJECTED");
+    assert!(error.message().contains("not addressed"), "{}", error.message());
+
+    // Only the invite's author revokes it
+    let result = eventually(
+        || member_client.call::<_, ActionHash>(SITES_ZOME, "revoke_invite", invite_hash.clone()),
+        |result| !is_not_found(result),
+    )
+    .await;
+    assert_eq!(rejection(result).code(), "NOT_AUTHORIZED");
+
+    wait_for_registration(&member_client).await;
+    let invites = eventually(
+        || member_client.call::<_, Vec<Record>>(SITES_ZOME, "get_my_invites", ()),
+        |invites| matches!(invites, Ok(invites) if !invites.is_empty()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(invites[0].action_address(), &invite_hash);
+
+    let _: Record = member_client
+        .call(SITES_ZOME, "join_site_with_invite", redeem())
+        .await
+        .unwrap();
+    assert_eq!(member_client.get_my_sites().await.unwrap().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from RUN_CONDUCTOR_TESTS=1 ./build.sh"]
+async fn ownership_transfers_need_the_outgoing_threshold() {
+    let (conductor, owner_client, owner) = connect().await;
+    let (co_owner_client, co_owner) = install_agent(&conductor, "-co-owner").await;
+    register_example(&owner_client, &owner, false, None).await;
+
+    let prepare = |owners: Vec<AgentPubKey>, threshold| {
+        owner_client.call::<_, SiteOwnershipTransfer>(
+            SITES_ZOME,
+            "prepare_ownership_transfer",
+            TransferOwnership {
+                site_id: "example.com".to_string(),
+                owners,
+                threshold,
+            },
+        )
+    };
+    let submit = |transfer: SiteOwnershipTransfer| {
+        owner_client.call::<_, ActionHash>(SITES_ZOME, "transfer_site_ownership", transfer)
+    };
+    let current_owners = || async {
+        owner_client
+            .call::<_, Option<SiteOwners>>(SITES_ZOME, "get_site_owners", "example.com".to_string())
+            .await
+            .unwrap()
+            .unwrap()
+    };
+
+    // Every incoming owner signs
+    let transfer = prepare(vec![owner.clone(), co_owner.clone()], 2).await.unwrap();
+    assert!(submit(transfer.clone()).await.is_err());
+    let signature: OwnerSignature = co_owner_client
+        .call(SITES_ZOME, "sign_ownership_transfer", transfer.clone())
+        .await
+        .unwrap();
+    submit(SiteOwnershipTransfer {
+        signatures: vec![signature],
+        ..transfer
+    })
+    .await
+    .unwrap();
+
+    let owners = current_owners().await;
+    assert_eq!(owners.owners, vec![owner.clone(), co_owner.clone()]);
+    assert_eq!(owners.threshold, 2);
+
+    // With a threshold of 2, one outgoing owner can't hand the site back alone
+    let transfer = prepare(vec![owner.clone()], 1).await.unwrap();
+    assert!(submit(transfer.clone()).await.is_err());
+    let signature: OwnerSignature = co_owner_client
+        .call(SITES_ZOME, "sign_ownership_transfer", transfer.clone())
+        .await
+        .unwrap();
+    submit(SiteOwnershipTransfer {
+        signatures: vec![signature],
+        ..transfer
+    })
+    .await
+    .unwrap();
+
+    let owners = current_owners().await;
+    assert_eq!(owners.owners, vec![owner]);
+    assert_eq!(owners.threshold, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from RUN_CONDUCTOR_TESTS=1 ./build.sh"]
+async fn only_site_owners_renew_memberships() {
+    let (conductor, owner_client, owner) = connect().await;
+    let (member_client, _member) = install_agent(&conductor, "-member").await;
+    register_example(&owner_client, &owner, false, Some(3600)).await;
+
+    wait_for_registration(&member_client).await;
+    let membership = member_client.join_site("example.com").await.unwrap();
+    let membership_hash = membership.action_address().clone();
+    let expires_at = now() + 7200;
+    let renewal = || RenewMembership {
+        membership_hash: membership_hash.clone(),
+        expires_at,
+    };
+
+    // The member can't extend their own membership
+    let error = rejection(
+        member_client
+            .call::<_, ActionHash>(SITES_ZOME, "renew_membership", renewal())
+            .await,
+    );
+    assert_eq!(error.code(), "NOT_AUTHORIZED");
+
+    let result = eventually(
+        || owner_client.call::<_, ActionHash>(SITES_ZOME, "renew_membership", renewal()),
+        |result| !is_not_found(result),
+    )
+    .await;
+    result.unwrap();
+
+    let validity: MembershipValidity = owner_client
+        .call(SITES_ZOME, "get_membership_validity", membership_hash)
+        .await
+        .unwrap();
+    assert_eq!(validity.valid_until, Some(expires_at));
+    assert!(validity.active);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from build.sh"]
+async fn membership_metadata_is_capped_and_member_only() {
+    let (conductor, client, _me) = connect().await;
+    let (other_client, _other) = install_agent(&conductor, "-other").await;
+    let membership = client.join_site("example.com").await.unwrap();
+    let metadata = |data: String| SetMetadata {
+        membership_hash: membership.action_address().clone(),
+        version: 1,
+        data,
+    };
+
+    let _: Record = client
+        .call(
+            SITES_ZOME,
+            "set_membership_metadata",
+            metadata("x".repeat(MAX_MEMBERSHIP_METADATA_BYTES)),
+        )
+        .await
+        .unwrap();
+
+    let error = rejection(
+        client
+            .call::<_, Record>(
+                SITES_ZOME,
+                "set_membership_metadata",
+                metadata("x".repeat(MAX_MEMBERSHIP_METADATA_BYTES + 1)),
+            )
+            .await,
+    );
+    assert_eq!(error.code(), "INVALID_INPUT");
+
+    let result = eventually(
+        || other_client.call::<_, Record>(SITES_ZOME, "set_membership_metadata", metadata("{}".to_string())),
+        |result| !is_not_found(result),
+    )
+    .await;
+    assert_eq!(rejection(result).code(), "NOT_AUTHORIZED");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs workdir/flowsta_identity_v1_5_happ.happ from build.sh"]
+async fn only_authors_leave_or_revoke() {
+    let (conductor, client, me) = connect().await;
+    let (other_client, _other) = install_agent(&conductor, "-other").await;
+    let membership = client.join_site("example.com").await.unwrap();
+    let membership_hash = membership.action_address().clone();
+    let consent_hash: ActionHash = client
+        .call(
+            SITES_ZOME,
+            "grant_consent",
+            GrantConsent {
+                site_id: "example.com".to_string(),
+                scopes: vec!["membership".to_string()],
+                expires_at: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    let result = eventually(
+        || other_client.leave_site(membership_hash.clone()),
+        |result| !is_not_found(result),
+    )
+    .await;
+    assert_eq!(rejection(result).code(), "NOT_AUTHORIZED");
+
+    let result = eventually(
+        || other_client.call::<_, ActionHash>(SITES_ZOME, "revoke_consent", consent_hash.clone()),
+        |result| !is_not_found(result),
+    )
+    .await;
+    assert_eq!(rejection(result).code(), "NOT_AUTHORIZED");
+
+    // The member leaves, deleting the membership and the links to it
+    client.leave_site(membership_hash).await.unwrap();
+    assert!(client.get_my_sites().await.unwrap().is_empty());
+    let member: bool = client
+        .call(
+            SITES_ZOME,
+            "is_agent_site_member",
+            AgentSiteQuery {
+                agent: me,
+                site_id: "example.com".to_string(),
+                blinding_key: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert!(!member);
+}
@@ -0,0 +1,566 @@
+//! Offline verification of Flowsta identity artifacts, for services (such as
+//! the Auth API) that can't run a conductor.
+//!
+//! Payloads are built by the integrity crates' own functions and signatures
+//! are checked the way the host's `verify_signature` checks them, so every
+//! result here matches zome validation byte for byte.
+
+use std::fmt;
+
+use ed25519_dalek::VerifyingKey;
+use hdi::prelude::{AgentPubKey, Signature};
+use serde::Serialize;
+
+pub use agent_linking_integrity::{sorted_agent_pair_bytes, IsSamePersonEntry};
+pub use sites_integrity::{
+    canonicalize_site_id, membership_credential_payload_bytes, ownership_transfer_payload_bytes,
+    pairwise_auth_payload, pairwise_binding_payload, pairwise_key_payload, MembershipCredential,
+    PairwiseProof, SiteOwnershipTransfer, PAIRWISE_DID_PREFIX,
+};
+pub use users_integrity::{
+    identity_export_signing_bytes, ExportedAgentLink, IdentityExport, SignedIdentityExport,
+    IDENTITY_EXPORT_VERSION,
+};
+
+/// Type prefix of the 39-byte AgentPubKey encoding
+const AGENT_PREFIX: [u8; 3] = [0x84, 0x20, 0x24];
+
+/// Why an artifact did not verify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// Not a valid 39-byte AgentPubKey (length, type prefix, location bytes or curve point)
+    InvalidAgentKey(String),
+    /// A signature does not verify against the agent it claims to be from
+    BadSignature(String),
+    /// The artifact is malformed, e.g. agents out of order or an unsupported version
+    Invalid(String),
+    /// A payload could not be msgpack-encoded
+    Serialization(String),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::InvalidAgentKey(reason) => write!(f, "Invalid agent key: {}", reason),
+            VerifyError::BadSignature(reason) => write!(f, "Bad signature: {}", reason),
+            VerifyError::Invalid(reason) => write!(f, "Invalid: {}", reason),
+            VerifyError::Serialization(reason) => write!(f, "Serialization failed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// The 4 location bytes holo_hash appends to a 32-byte core:
+/// BLAKE2b-128 of the core, folded to 4 bytes with XOR
+fn dht_location_bytes(core: &[u8]) -> [u8; 4] {
+    let hash = blake2b_simd::Params::new().hash_length(16).hash(core);
+    let mut location = [0u8; 4];
+    for chunk in hash.as_bytes().chunks(4) {
+        for (byte, folded) in location.iter_mut().zip(chunk) {
+            *byte ^= folded;
+        }
+    }
+    location
+}
+
+/// Parse the 39-byte AgentPubKey encoding (3-byte type prefix, 32-byte
+/// Ed25519 key, 4-byte location), checking the prefix and location bytes
+pub fn agent_from_raw_39(bytes: &[u8]) -> Result<AgentPubKey, VerifyError> {
+    if bytes.len() != 39 {
+        return Err(VerifyError::InvalidAgentKey(format!(
+            "expected 39 bytes, got {}",
+            bytes.len()
+        )));
+    }
+    if bytes[..3] != AGENT_PREFIX {
+        return Err(VerifyError::InvalidAgentKey("not an AgentPubKey type prefix".to_string()));
+    }
+    if bytes[35..] != dht_location_bytes(&bytes[3..35]) {
+        return Err(VerifyError::InvalidAgentKey(
+            "location bytes do not match the key".to_string(),
+        ));
+    }
+    Ok(AgentPubKey::from_raw_39(bytes.to_vec()))
+}
+
+/// AgentPubKey for a raw Ed25519 public key (adds the type prefix and location bytes)
+pub fn agent_from_ed25519(key: &[u8; 32]) -> AgentPubKey {
+    let mut bytes = AGENT_PREFIX.to_vec();
+    bytes.extend_from_slice(key);
+    bytes.extend_from_slice(&dht_location_bytes(key));
+    AgentPubKey::from_raw_39(bytes)
+}
+
+/// The Ed25519 key inside an AgentPubKey, after checking its 39-byte encoding
+fn verifying_key(agent: &AgentPubKey) -> Result<VerifyingKey, VerifyError> {
+    agent_from_raw_39(agent.get_raw_39())?;
+    let mut key = [0u8; 32];
+    key.copy_from_slice(agent.get_raw_32());
+    VerifyingKey::from_bytes(&key)
+        .map_err(|_| VerifyError::InvalidAgentKey("not an Ed25519 public key".to_string()))
+}
+
+/// Check a signature over exactly `data`, as made by `sign_raw` or any plain
+/// Ed25519 signer. Uses strict verification (canonical signatures, no
+/// small-order keys), like the conductor's libsodium.
+pub fn verify_raw_signature(
+    agent: &AgentPubKey,
+    signature: &Signature,
+    data: &[u8],
+) -> Result<bool, VerifyError> {
+    let key = verifying_key(agent)?;
+    let signature = ed25519_dalek::Signature::from_bytes(&signature.0);
+    Ok(key.verify_strict(data, &signature).is_ok())
+}
+
+/// Offline equivalent of the host `verify_signature(agent, signature, data)`.
+///
+/// The zome `sign` and `verify_signature` functions msgpack-encode `data`
+/// first, so a `Vec<u8>` payload is signed as a msgpack array of integers,
+/// not as the raw bytes. This encodes the same way before checking.
+pub fn verify_signature<D>(
+    agent: &AgentPubKey,
+    signature: &Signature,
+    data: D,
+) -> Result<bool, VerifyError>
+where
+    D: Serialize + fmt::Debug,
+{
+    let encoded = holochain_serialized_bytes::encode(&data)
+        .map_err(|e| VerifyError::Serialization(e.to_string()))?;
+    verify_raw_signature(agent, signature, &encoded)
+}
+
+/// Check one half of a link: `signer`'s signature over the sorted pair it forms with `other`.
+/// This is what `create_direct_link` checks before committing.
+pub fn verify_link_signature(
+    signer: &AgentPubKey,
+    other: &AgentPubKey,
+    signature: &Signature,
+) -> Result<bool, VerifyError> {
+    let payload = sorted_agent_pair_bytes(signer, other)
+        .map_err(|e| VerifyError::Serialization(e.to_string()))?;
+    verify_signature(signer, signature, payload)
+}
+
+/// Check an IsSamePersonEntry the way agent_linking validation does:
+/// 1. Two different agents, agent_a < agent_b
+/// 2. Both signatures verify over `sorted_agent_pair_bytes`
+///
+/// (That the author is one of the two agents is a property of the action, not the entry.)
+pub fn verify_is_same_person(entry: &IsSamePersonEntry) -> Result<(), VerifyError> {
+    if entry.agent_a == entry.agent_b {
+        return Err(VerifyError::Invalid(
+            "agent_a and agent_b must be different agents".to_string(),
+        ));
+    }
+
+    if entry.agent_a >= entry.agent_b {
+        return Err(VerifyError::Invalid(
+            "agent_a must be lexicographically smaller than agent_b".to_string(),
+        ));
+    }
+
+    if !verify_link_signature(&entry.agent_a, &entry.agent_b, &entry.signature_a)? {
+        return Err(VerifyError::BadSignature(
+            "signature_a does not verify against agent_a".to_string(),
+        ));
+    }
+
+    if !verify_link_signature(&entry.agent_b, &entry.agent_a, &entry.signature_b)? {
+        return Err(VerifyError::BadSignature(
+            "signature_b does not verify against agent_b".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check an agent link carried in an IdentityExport, as an IsSamePersonEntry
+pub fn verify_exported_link(link: &ExportedAgentLink) -> Result<(), VerifyError> {
+    verify_is_same_person(&IsSamePersonEntry {
+        agent_a: link.agent_a.clone(),
+        signature_a: link.signature_a.clone(),
+        agent_b: link.agent_b.clone(),
+        signature_b: link.signature_b.clone(),
+        created_at: link.created_at,
+        delegation: None,
+    })
+}
+
+/// Check an auth challenge response: the agent's signature over the
+/// challenge bytes, made with the zome `sign` function
+pub fn verify_challenge_response(
+    agent: &AgentPubKey,
+    challenge: &[u8],
+    signature: &Signature,
+) -> Result<bool, VerifyError> {
+    verify_signature(agent, signature, challenge.to_vec())
+}
+
+/// Check a SignedIdentityExport the way `check_signed_export` does: the
+/// exporting agent's signature over the shipped bytes, a supported version,
+/// at least one profile, and a lineage of exports that verify the same way
+/// and join up hop by hop. Returns the decoded export.
+/// Carried agent links are checked separately with `verify_exported_link`.
+pub fn verify_signed_export(signed: &SignedIdentityExport) -> Result<IdentityExport, VerifyError> {
+    let export: IdentityExport = holochain_serialized_bytes::decode(&signed.export_bytes)
+        .map_err(|e| VerifyError::Serialization(e.to_string()))?;
+
+    let payload = identity_export_signing_bytes(&signed.export_bytes);
+    if !verify_signature(&export.agent, &signed.signature, payload)? {
+        return Err(VerifyError::BadSignature(
+            "Export signature does not verify against the exporting agent".to_string(),
+        ));
+    }
+
+    if export.version == 0 || export.version > IDENTITY_EXPORT_VERSION {
+        return Err(VerifyError::Invalid(format!(
+            "Unsupported export version {}",
+            export.version
+        )));
+    }
+
+    if export.profile_history.is_empty() {
+        return Err(VerifyError::Invalid("Export has no profile".to_string()));
+    }
+
+    for signed_earlier in &export.lineage {
+        let earlier = verify_signed_export(signed_earlier)?;
+        if earlier.dna_hash == export.dna_hash {
+            return Err(VerifyError::Invalid(
+                "Lineage export comes from the exporting DNA".to_string(),
+            ));
+        }
+        if earlier.agent != export.agent && earlier.recipient.as_ref() != Some(&export.agent) {
+            return Err(VerifyError::Invalid(
+                "Lineage export was not meant for the exporting agent".to_string(),
+            ));
+        }
+    }
+
+    Ok(export)
+}
+
+/// Check what a membership credential proves on its own: a canonical site,
+/// the issuer's signature, and not expired at `now` (seconds).
+/// Whether the issuer still owns the site can only be answered from the DHT.
+pub fn verify_membership_credential(
+    credential: &MembershipCredential,
+    now: i64,
+) -> Result<(), VerifyError> {
+    let payload = &credential.payload;
+
+    if canonicalize_site_id(&payload.site_id).ok().as_deref() != Some(payload.site_id.as_str()) {
+        return Err(VerifyError::Invalid("site_id is not canonical".to_string()));
+    }
+
+    if !verify_signature(
+        &credential.issuer,
+        &credential.signature,
+        membership_credential_payload_bytes(payload),
+    )? {
+        return Err(VerifyError::BadSignature(
+            "Signature does not verify against the issuer".to_string(),
+        ));
+    }
+
+    if now >= payload.expires_at {
+        return Err(VerifyError::Invalid("Credential has expired".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Pairwise DID an agent derives from its signature over
+/// `pairwise_key_payload(site_id)`: the BLAKE2b-256 hash of that signature
+/// seeds the per-site Ed25519 key, whose public half is hex encoded
+pub fn pairwise_id_from_seed_signature(seed_signature: &Signature) -> String {
+    let digest = blake2b_simd::Params::new()
+        .hash_length(32)
+        .hash(&seed_signature.0);
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(digest.as_bytes());
+    let key = ed25519_dalek::SigningKey::from_bytes(&seed).verifying_key();
+    let hex: String = key
+        .as_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}{}", PAIRWISE_DID_PREFIX, hex)
+}
+
+/// The Ed25519 key a pairwise DID names, as an AgentPubKey
+pub fn pairwise_key_from_id(pairwise_id: &str) -> Result<AgentPubKey, VerifyError> {
+    let invalid = || VerifyError::Invalid(format!("'{}' is not a pairwise DID", pairwise_id));
+
+    let hex = pairwise_id
+        .strip_prefix(PAIRWISE_DID_PREFIX)
+        .filter(|hex| hex.len() == 64)
+        .ok_or_else(invalid)?;
+
+    let mut key = [0u8; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(agent_from_ed25519(&key))
+}
+
+/// Check a pairwise proof the way `check_pairwise_proof` does: canonical
+/// site, and the binding payload signed by both the agent (zome `sign`)
+/// and the key the pairwise ID names (raw Ed25519)
+pub fn verify_pairwise_proof(proof: &PairwiseProof) -> Result<(), VerifyError> {
+    match canonicalize_site_id(&proof.site_id) {
+        Ok(canonical) if canonical == proof.site_id => {}
+        Ok(canonical) => {
+            return Err(VerifyError::Invalid(format!(
+                "site_id must be canonical: expected '{}', got '{}'",
+                canonical, proof.site_id
+            )))
+        }
+        Err(reason) => return Err(VerifyError::Invalid(reason)),
+    }
+
+    let pairwise_key = pairwise_key_from_id(&proof.pairwise_id)?;
+    let payload = pairwise_binding_payload(&proof.site_id, &proof.pairwise_id, &proof.agent);
+
+    if !verify_signature(&proof.agent, &proof.proof, payload.clone())? {
+        return Err(VerifyError::BadSignature(
+            "pairwise proof does not verify against the agent".to_string(),
+        ));
+    }
+
+    if !verify_raw_signature(&pairwise_key, &proof.key_proof, &payload)? {
+        return Err(VerifyError::BadSignature(
+            "pairwise proof does not verify against the pairwise key".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check a signature made by the zome's `sign_as_pairwise` for `site_id`
+pub fn verify_pairwise_signature(
+    site_id: &str,
+    pairwise_id: &str,
+    data: &[u8],
+    signature: &Signature,
+) -> Result<bool, VerifyError> {
+    let pairwise_key = pairwise_key_from_id(pairwise_id)?;
+    verify_raw_signature(
+        &pairwise_key,
+        signature,
+        &pairwise_auth_payload(site_id, data),
+    )
+}
+
+/// Check every signature on an ownership transfer (no duplicates, each over
+/// `ownership_transfer_payload_bytes`) and return the signers. Whether they
+/// meet the outgoing threshold depends on the previous owner set on the DHT.
+pub fn verify_ownership_transfer_signatures(
+    transfer: &SiteOwnershipTransfer,
+) -> Result<Vec<AgentPubKey>, VerifyError> {
+    let payload = ownership_transfer_payload_bytes(transfer);
+    let mut signers: Vec<AgentPubKey> = Vec::new();
+    for owner_signature in &transfer.signatures {
+        if signers.contains(&owner_signature.owner) {
+            return Err(VerifyError::Invalid(format!(
+                "Duplicate signature by {}",
+                owner_signature.owner
+            )));
+        }
+        if !verify_signature(&owner_signature.owner, &owner_signature.signature, payload.clone())? {
+            return Err(VerifyError::BadSignature(format!(
+                "Transfer signature by {} does not verify",
+                owner_signature.owner
+            )));
+        }
+        signers.push(owner_signature.owner.clone());
+    }
+    Ok(signers)
+}
+
+#[cfg(test)]
+mod tests {
+    //! Known-answer vectors, generated independently of this crate (Python
+    //! `hashlib` and `cryptography`, msgpack encoded by hand). The agents are
+    //! the Ed25519 seeds [1; 32] (A) and [2; 32] (B); signatures are what the
+    //! host `sign` returns for them, Ed25519 over the msgpack-encoded data.
+    use super::*;
+
+    const AGENT_A: &str = concat!(
+        "8420248a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf3748801b4",
+        "0f6f5c85805260",
+    );
+    const AGENT_B: &str = concat!(
+        "8420248139770ea87d175f56a35466c34c7ecccb8d8a91b4ee37a25df60f5b8f",
+        "c9b39423966a95",
+    );
+    /// `sorted_agent_pair_bytes` of the two agents (B sorts first)
+    const PAIR: &str = concat!(
+        "8420248139770ea87d175f56a35466c34c7ecccb8d8a91b4ee37a25df60f5b8f",
+        "c9b39423966a958420248a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94",
+        "121bf3748801b40f6f5c85805260",
+    );
+    /// What the host `sign` signs for that payload: a msgpack array of integers
+    const PAIR_MSGPACK: &str = concat!(
+        "dc004ecc842024cc8139770ecca87d175f56cca35466ccc34c7ecccccccbcc8d",
+        "cc8acc91ccb4ccee37cca25dccf60f5bcc8fccc9ccb3cc9423cc966acc95cc84",
+        "2024cc8acc88cce3ccdd7409ccf1cc95ccfd52ccdb2d3cccba5d72ccca6709cc",
+        "bf1dcc94121bccf374cc8801ccb40f6f5ccc85cc805260",
+    );
+    const SIGNATURE_A: &str = concat!(
+        "430fd8b27b056ed12a47f90915a72177d5e845119d29b778ba96513002a1b908",
+        "cdfa3d951d6f1c30d95d10cd45eac1622008f2b22cbf0510e08837ff2e751b02",
+    );
+    const SIGNATURE_B: &str = concat!(
+        "0a46356da470b89dc49fbd830da0ea3f34ea29fc95e4d9f90a718fece71f3511",
+        "11e890e0f782aa6358a6fd14b91facff5d2493d62cc855a544f975870aea3c02",
+    );
+    /// Agent A's `sign` over `pairwise_key_payload("example.com")`
+    const PAIRWISE_SEED_SIGNATURE: &str = concat!(
+        "7afd240a0c61069b57e5d7bf076dbef630a6d34301b2ddd36720766ff015c507",
+        "568d5da070dd637ddc97d3cd9ae55e39b73502d1e17b10cc2351b3e055f15008",
+    );
+    const PAIRWISE_ID: &str =
+        "did:flowsta:pairwise:fb8a45924d70e69ceb5e59c11d77a412c37264dc0dae5b661280884365a302f4";
+    const PAIRWISE_KEY: &str = concat!(
+        "842024fb8a45924d70e69ceb5e59c11d77a412c37264dc0dae5b661280884365",
+        "a302f4d6d86870",
+    );
+    const PAIRWISE_BINDING: &str = concat!(
+        "666c6f777374612d70616972776973652d62696e64696e672d76313a0000000b",
+        "6578616d706c652e636f6d000000556469643a666c6f777374613a7061697277",
+        "6973653a66623861343539323464373065363963656235653539633131643737",
+        "6134313263333732363464633064616535623636313238303838343336356133",
+        "303266348420248a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf3",
+        "748801b40f6f5c85805260",
+    );
+    const PAIRWISE_PROOF: &str = concat!(
+        "ba1a2e06b84dbe573ceeb81872f18eb4362d5183b1bcbf5624d092b902760c9b",
+        "13607219e02e132a74afd61f7e3748d7e0eb1943e0da8b0e7d33331faa170208",
+    );
+    const PAIRWISE_KEY_PROOF: &str = concat!(
+        "1db84ad65654f3de77393650b6db80c7dac9630fd7f80fe70836b9ab44230e6f",
+        "c1164e1160eb7a558fef701aeb51b0d7e88bf88bde002335ea1e83d5e29b1d01",
+    );
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn agent(hex: &str) -> AgentPubKey {
+        agent_from_raw_39(&bytes(hex)).unwrap()
+    }
+
+    fn signature(hex: &str) -> Signature {
+        let mut signature = [0u8; 64];
+        signature.copy_from_slice(&bytes(hex));
+        Signature(signature)
+    }
+
+    #[test]
+    fn location_bytes_match_holo_hash() {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&bytes(AGENT_A)[3..35]);
+        assert_eq!(
+            agent_from_ed25519(&key).get_raw_39(),
+            bytes(AGENT_A).as_slice()
+        );
+
+        let mut corrupted = bytes(AGENT_A);
+        corrupted[38] ^= 1;
+        assert!(agent_from_raw_39(&corrupted).is_err());
+    }
+
+    #[test]
+    fn sorts_the_agent_pair() {
+        let (a, b) = (agent(AGENT_A), agent(AGENT_B));
+        assert_eq!(sorted_agent_pair_bytes(&a, &b).unwrap(), bytes(PAIR));
+        assert_eq!(sorted_agent_pair_bytes(&b, &a).unwrap(), bytes(PAIR));
+    }
+
+    #[test]
+    fn encodes_payloads_like_sign() {
+        assert_eq!(
+            holochain_serialized_bytes::encode(&bytes(PAIR)).unwrap(),
+            bytes(PAIR_MSGPACK)
+        );
+    }
+
+    #[test]
+    fn verifies_host_signatures_strictly() {
+        let (a, b) = (agent(AGENT_A), agent(AGENT_B));
+        assert_eq!(
+            verify_raw_signature(&a, &signature(SIGNATURE_A), &bytes(PAIR_MSGPACK)),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_link_signature(&a, &b, &signature(SIGNATURE_A)),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_link_signature(&b, &a, &signature(SIGNATURE_B)),
+            Ok(true)
+        );
+        // `sign` covers the msgpack encoding, not the raw pair
+        assert_eq!(
+            verify_raw_signature(&a, &signature(SIGNATURE_A), &bytes(PAIR)),
+            Ok(false)
+        );
+        assert_eq!(
+            verify_link_signature(&b, &a, &signature(SIGNATURE_A)),
+            Ok(false)
+        );
+
+        let entry = IsSamePersonEntry {
+            agent_a: b.clone(),
+            signature_a: signature(SIGNATURE_B),
+            agent_b: a.clone(),
+            signature_b: signature(SIGNATURE_A),
+            created_at: 0,
+            delegation: None,
+        };
+        assert_eq!(verify_is_same_person(&entry), Ok(()));
+    }
+
+    #[test]
+    fn derives_pairwise_ids() {
+        let a = agent(AGENT_A);
+        let seed_signature = signature(PAIRWISE_SEED_SIGNATURE);
+        assert_eq!(
+            verify_signature(&a, &seed_signature, pairwise_key_payload("example.com")),
+            Ok(true)
+        );
+        assert_eq!(
+            pairwise_id_from_seed_signature(&seed_signature),
+            PAIRWISE_ID
+        );
+        assert_eq!(pairwise_key_from_id(PAIRWISE_ID), Ok(agent(PAIRWISE_KEY)));
+    }
+
+    #[test]
+    fn verifies_pairwise_proofs() {
+        let a = agent(AGENT_A);
+        assert_eq!(
+            pairwise_binding_payload("example.com", PAIRWISE_ID, &a),
+            bytes(PAIRWISE_BINDING)
+        );
+
+        let mut proof = PairwiseProof {
+            agent: a,
+            site_id: "example.com".to_string(),
+            pairwise_id: PAIRWISE_ID.to_string(),
+            proof: signature(PAIRWISE_PROOF),
+            key_proof: signature(PAIRWISE_KEY_PROOF),
+        };
+        assert_eq!(verify_pairwise_proof(&proof), Ok(()));
+
+        proof.site_id = "other.example".to_string();
+        assert!(verify_pairwise_proof(&proof).is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::BTreeSet;
+
+use hdk::prelude::*;
+use agent_linking_integrity::*;
+use identity_errors::IdentityError;
+
+#[hdk_dependent_entry_types]
+enum EntryZomes {
+    IntegrityAgentLinking(agent_linking_integrity::EntryTypes),
+}
+
+// ── Public Functions ────────────────────────────────────────────────
+
+/// Get the non-revoked IsSamePersonEntry records of an agent, with both
+/// signatures (e.g. to carry links over to a new DNA version).
+/// Follows links from the agent's pubkey and filters out deleted entries.
+#[hdk_extern]
+pub fn get_link_records(agent: AgentPubKey) -> ExternResult<Vec<Record>> {
+    let links = get_links(
+        LinkQuery::try_new(agent.clone(), LinkTypes::AgentToIsSamePerson)?,
+        GetStrategy::default(),
+    )?;
+
+    let mut records: Vec<Record> = Vec::new();
+
+    for link in links {
+        let action_hash = match ActionHash::try_from(link.target.clone()) {
+            Ok(hash) => hash,
+            Err(_) => continue,
+        };
+
+        // Get the entry details to check for Deletes (coordination layer)
+        let details = match get_details(action_hash, GetOptions::default())? {
+            Some(details) => details,
+            None => continue,
+        };
+
+        match details {
+            Details::Record(record_details) => {
+                // Check if the entry has been deleted (revoked)
+                if !record_details.deletes.is_empty() {
+                    continue;
+                }
+
+                // Both agents link the same entry; keep one copy
+                let is_new = !records
+                    .iter()
+                    .any(|record| record.action_address() == record_details.record.action_address());
+                if is_new {
+                    records.push(record_details.record);
+                }
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(records)
+}
+
+/// Get all agents linked to a given agent (non-deleted entries only).
+/// Returns the OTHER agent from each non-revoked IsSamePersonEntry.
+#[hdk_extern]
+pub fn get_linked_agents(agent: AgentPubKey) -> ExternResult<Vec<AgentPubKey>> {
+    let mut linked_agents: Vec<AgentPubKey> = Vec::new();
+
+    for record in get_link_records(agent.clone())? {
+        // Extract the entry and find the OTHER agent
+        if let Some(entry) = record.entry().as_option() {
+            if let Ok(is_same_person) = IsSamePersonEntry::try_from(entry) {
+                let other_agent = if is_same_person.agent_a == agent {
+                    is_same_person.agent_b.clone()
+                } else {
+                    is_same_person.agent_a.clone()
+                };
+
+                if !linked_agents.contains(&other_agent) {
+                    linked_agents.push(other_agent);
+                }
+            }
+        }
+    }
+
+    Ok(linked_agents)
+}
+
+/// Check if two specific agents are linked (non-deleted entry exists).
+#[hdk_extern]
+pub fn are_agents_linked(agents: AgentPair) -> ExternResult<bool> {
+    let linked = get_linked_agents(agents.agent_a.clone())?;
+    Ok(linked.contains(&agents.agent_b))
+}
+
+/// Revoke a link by deleting the IsSamePersonEntry creation action.
+/// Only one of the two agents in the entry, or the delegate that published
+/// it, can revoke it.
+/// Returns the ActionHash of the Delete action.
+#[hdk_extern]
+pub fn revoke_link(entry_action_hash: ActionHash) -> ExternResult<ActionHash> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    // Get the entry to verify the caller is one of the agents
+    let record = get(entry_action_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Entry not found".into())))?;
+
+    let entry = record
+        .entry()
+        .as_option()
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("No entry data found".into())))?;
+
+    let is_same_person = IsSamePersonEntry::try_from(entry).map_err(|_| {
+        wasm_error!(IdentityError::WrongEntryType("Entry is not an IsSamePersonEntry".into()))
+    })?;
+
+    if my_pub_key != is_same_person.agent_a
+        && my_pub_key != is_same_person.agent_b
+        && record.action().author() != &my_pub_key
+    {
+        return Err(wasm_error!(IdentityError::NotAuthorized(
+            "Only one of the two linked agents or its publisher can revoke this link".into()
+        )));
+    }
+
+    // Delete the original creation action
+    ensure_signal_grant()?;
+    let delete_hash = delete_entry(entry_action_hash)?;
+
+    Ok(delete_hash)
+}
+
+/// Create an IsSamePersonEntry with an externally-provided signature for one agent.
+///
+/// Designed for desktop apps where the API mediates the linking.
+/// The desktop signs the key pair locally, sends the signature to the API,
+/// and the web agent (caller) creates the entry with both signatures.
+///
+/// Security: The other agent's signature is verified before committing.
+#[hdk_extern]
+pub fn create_direct_link(input: DirectLinkInput) -> ExternResult<ActionHash> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    if my_pub_key == input.other_agent {
+        return Err(wasm_error!(IdentityError::InvalidInput(
+            "Cannot link an agent to itself".into()
+        )));
+    }
+
+    // Verify the other agent's signature over the sorted key pair
+    let payload = sorted_agent_pair_bytes(&my_pub_key, &input.other_agent)?;
+
+    if !verify_signature(
+        input.other_agent.clone(),
+        input.other_signature.clone(),
+        payload.clone(),
+    )? {
+        return Err(wasm_error!(IdentityError::InvalidSignature(
+            "Other agent's signature is invalid".into()
+        )));
+    }
+
+    // Sign our half
+    let my_signature = sign(my_pub_key.clone(), payload)?;
+
+    // Construct the IsSamePersonEntry with canonical ordering (agent_a < agent_b)
+    let mut keys = vec![
+        (my_pub_key.clone(), my_signature),
+        (input.other_agent.clone(), input.other_signature),
+    ];
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let now = sys_time()?;
+    let now_secs = now.as_seconds_and_nanos().0;
+
+    let entry = IsSamePersonEntry {
+        agent_a: keys[0].0.clone(),
+        signature_a: keys[0].1.clone(),
+        agent_b: keys[1].0.clone(),
+        signature_b: keys[1].1.clone(),
+        created_at: now_secs,
+        delegation: None,
+    };
+
+    commit_link(entry)
+}
+
+/// Re-create IsSamePersonEntry links carried over from a previous DNA
+/// version (see users.import_data). The signatures are over the key pair
+/// only, so they verify on any DNA. Each link must be one of `signer`'s
+/// (the agent that signed the export); when the caller is a new key rather
+/// than `signer`, the link must carry `signer`'s delegation to the caller.
+///
+/// Links that are already held here are passed over; links that don't
+/// verify or aren't authorized are skipped and reported, the rest are created.
+#[hdk_extern]
+pub fn import_links(input: ImportLinksInput) -> ExternResult<LinksImport> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    let mut report = LinksImport::default();
+    for entry in input.links {
+        match link_import_problem(&entry, &input.signer, &my_pub_key)? {
+            Some(reason) => report.skipped.push(SkippedLink {
+                agent_a: entry.agent_a,
+                agent_b: entry.agent_b,
+                reason,
+            }),
+            None => {
+                let pair = AgentPair {
+                    agent_a: entry.agent_a.clone(),
+                    agent_b: entry.agent_b.clone(),
+                };
+                if !are_agents_linked(pair)? {
+                    report.imported.push(commit_link(entry)?);
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Why an imported link can't be created by `me`, if it can't
+fn link_import_problem(
+    entry: &IsSamePersonEntry,
+    signer: &AgentPubKey,
+    me: &AgentPubKey,
+) -> ExternResult<Option<String>> {
+    if signer != &entry.agent_a && signer != &entry.agent_b {
+        return Ok(Some("Not a link of the exporting agent".to_string()));
+    }
+
+    let payload = sorted_agent_pair_bytes(&entry.agent_a, &entry.agent_b)?;
+    let both_signed = verify_signature(
+        entry.agent_a.clone(),
+        entry.signature_a.clone(),
+        payload.clone(),
+    )? && verify_signature(entry.agent_b.clone(), entry.signature_b.clone(), payload)?;
+    if entry.agent_a >= entry.agent_b || !both_signed {
+        return Ok(Some("Link signatures do not verify".to_string()));
+    }
+
+    if me != &entry.agent_a && me != &entry.agent_b {
+        let delegated = match &entry.delegation {
+            Some(delegation) if &delegation.signer == signer => verify_signature(
+                delegation.signer.clone(),
+                delegation.signature.clone(),
+                link_delegation_payload(&entry.agent_a, &entry.agent_b, me)?,
+            )?,
+            _ => false,
+        };
+        if !delegated {
+            return Ok(Some("The exporting agent did not delegate this link to this agent".to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Sign a delegation letting `delegate` publish one of the caller's links
+/// (see users.export_identity with a recipient)
+#[hdk_extern]
+pub fn sign_link_delegation(input: LinkDelegationInput) -> ExternResult<LinkDelegation> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    if my_pub_key != input.agent_a && my_pub_key != input.agent_b {
+        return Err(wasm_error!(IdentityError::NotAuthorized(
+            "Only one of the two linked agents can delegate this link".into()
+        )));
+    }
+
+    let payload = link_delegation_payload(&input.agent_a, &input.agent_b, &input.delegate)?;
+    Ok(LinkDelegation {
+        signature: sign(my_pub_key.clone(), payload)?,
+        signer: my_pub_key,
+    })
+}
+
+/// Commit an IsSamePersonEntry and link it from both agents
+fn commit_link(entry: IsSamePersonEntry) -> ExternResult<ActionHash> {
+    ensure_signal_grant()?;
+
+    // Commit the entry
+    let entry_hash = create_entry(&EntryZomes::IntegrityAgentLinking(
+        EntryTypes::IsSamePerson(entry.clone()),
+    ))?;
+
+    // Create lookup links from BOTH agents' pubkeys to the entry
+    create_link(
+        entry.agent_a.clone(),
+        entry_hash.clone(),
+        LinkTypes::AgentToIsSamePerson,
+        (),
+    )?;
+    create_link(
+        entry.agent_b.clone(),
+        entry_hash.clone(),
+        LinkTypes::AgentToIsSamePerson,
+        (),
+    )?;
+
+    Ok(entry_hash)
+}
+
+// ── Signals ─────────────────────────────────────────────────────────
+
+/// Tag of the cap grant that lets other agents call recv_remote_signal
+const LINK_SIGNALS_GRANT_TAG: &str = "link_signals";
+
+/// Let linked agents deliver link signals to this agent
+#[hdk_extern]
+pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
+    ensure_signal_grant()?;
+
+    Ok(InitCallbackResult::Pass)
+}
+
+/// Commit the unrestricted recv_remote_signal grant unless this chain holds
+/// it already. init creates it, but cells that ran init before it did never
+/// got one, so every link change here also makes sure it exists.
+fn ensure_signal_grant() -> ExternResult<()> {
+    let grants = query(
+        ChainQueryFilter::new()
+            .entry_type(EntryType::CapGrant)
+            .include_entries(true),
+    )?;
+    let granted = grants.iter().any(|record| {
+        matches!(record.entry().as_option(), Some(Entry::CapGrant(grant)) if grant.tag == LINK_SIGNALS_GRANT_TAG)
+    });
+    if granted {
+        return Ok(());
+    }
+
+    let mut functions = BTreeSet::new();
+    functions.insert((zome_info()?.name, "recv_remote_signal".into()));
+    create_cap_grant(CapGrantEntry {
+        tag: LINK_SIGNALS_GRANT_TAG.into(),
+        access: CapAccess::Unrestricted,
+        functions: GrantedFunctions::Listed(functions),
+    })?;
+
+    Ok(())
+}
+
+/// Emit (and forward to the other agent) a signal for each committed link change
+#[hdk_extern(infallible)]
+pub fn post_commit(committed_actions: Vec<SignedActionHashed>) {
+    for action in committed_actions {
+        if let Err(err) = signal_action(action) {
+            error!("Error signaling new action: {:?}", err);
+        }
+    }
+}
+
+fn signal_action(action: SignedActionHashed) -> ExternResult<()> {
+    // Only IsSamePersonEntry creates and deletes are looked up. A delete
+    // doesn't name the entry type; the deleted action (on this chain) does.
+    let is_same_person: EntryType = UnitEntryTypes::IsSamePerson.try_into()?;
+    let entry_type = match action.action() {
+        Action::Delete(delete) => must_get_action(delete.deletes_address.clone())?
+            .action()
+            .entry_type()
+            .cloned(),
+        other => other.entry_type().cloned(),
+    };
+    if entry_type != Some(is_same_person) {
+        return Ok(());
+    }
+
+    let signal = match action.action() {
+        Action::Create(_) => {
+            let entry_action_hash = action.as_hash().clone();
+            is_same_person_entry(&entry_action_hash)?.map(|entry| AgentLinkingSignal::LinkCreated {
+                entry_action_hash,
+                agent_a: entry.agent_a,
+                agent_b: entry.agent_b,
+            })
+        }
+        Action::Delete(delete) => {
+            let entry_action_hash = delete.deletes_address.clone();
+            is_same_person_entry(&entry_action_hash)?.map(|entry| AgentLinkingSignal::LinkRevoked {
+                entry_action_hash,
+                agent_a: entry.agent_a,
+                agent_b: entry.agent_b,
+            })
+        }
+        _ => None,
+    };
+    let Some(signal) = signal else {
+        return Ok(());
+    };
+
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let (agent_a, agent_b) = signal.agents();
+    // The other agent, or both when a delegate published the link
+    let other_agents: Vec<AgentPubKey> = [agent_a, agent_b]
+        .into_iter()
+        .filter(|agent| *agent != &my_pub_key)
+        .cloned()
+        .collect();
+
+    emit_signal(&signal)?;
+    // Best effort: the other agent may be offline
+    send_remote_signal(&signal, other_agents)
+}
+
+/// The IsSamePersonEntry an action created, if it created one
+fn is_same_person_entry(action_hash: &ActionHash) -> ExternResult<Option<IsSamePersonEntry>> {
+    let Some(record) = get(action_hash.clone(), GetOptions::default())? else {
+        return Ok(None);
+    };
+    Ok(record
+        .entry()
+        .as_option()
+        .and_then(|entry| IsSamePersonEntry::try_from(entry).ok()))
+}
+
+/// Re-emit a link signal from the other agent to this agent's clients.
+/// Ignored unless the link is between the sender and this agent.
+#[hdk_extern]
+pub fn recv_remote_signal(signal: AgentLinkingSignal) -> ExternResult<()> {
+    let sender = call_info()?.provenance;
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let (agent_a, agent_b) = signal.agents();
+    let between_us = (agent_a == &sender && agent_b == &my_pub_key)
+        || (agent_b == &sender && agent_a == &my_pub_key);
+    if !between_us {
+        return Ok(());
+    }
+
+    emit_signal(signal)
+}
@@ -0,0 +1,330 @@
+use hdi::prelude::*;
+
+/// A pairwise "is-same-person" attestation. Both agents sign the
+/// sorted pair of keys, then EACH agent commits this SAME entry
+/// to their own source chain.
+///
+/// For 3+ agents, create multiple pairwise entries (A↔B, A↔C, B↔C).
+/// Revocation: use Holochain's Delete action on the creation action.
+///
+/// This zome is fully generic — no Flowsta-specific fields.
+/// Any Holochain app can include it in their DNA for pairwise agent linking.
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct IsSamePersonEntry {
+    /// First agent in the pair (deterministic: always the lexicographically smaller key)
+    pub agent_a: AgentPubKey,
+
+    /// First agent's signature over the sorted agent key bytes
+    pub signature_a: Signature,
+
+    /// Second agent in the pair (deterministic: always the lexicographically larger key)
+    pub agent_b: AgentPubKey,
+
+    /// Second agent's signature over the sorted agent key bytes
+    pub signature_b: Signature,
+
+    /// Timestamp of when the entry was finalised
+    pub created_at: i64,
+
+    /// Set when the author is neither agent (e.g. an agent's new key
+    /// republishing its links after a migration): one agent's permission
+    /// for the author to publish this link
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<LinkDelegation>,
+}
+
+/// One agent's signature (zome `sign`) over `link_delegation_payload`,
+/// letting another agent publish a link it is part of
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkDelegation {
+    /// agent_a or agent_b
+    pub signer: AgentPubKey,
+    pub signature: Signature,
+}
+
+/// Links exported by `signer` to re-create here (see import_links)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportLinksInput {
+    /// The agent that exported the links; each must be one of its links
+    pub signer: AgentPubKey,
+    /// Delegated to the caller when it is not `signer`
+    pub links: Vec<IsSamePersonEntry>,
+}
+
+/// What import_links did with each link
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LinksImport {
+    /// Action hashes of the links created
+    pub imported: Vec<ActionHash>,
+    /// Links that couldn't be re-created, with the reason
+    pub skipped: Vec<SkippedLink>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SkippedLink {
+    pub agent_a: AgentPubKey,
+    pub agent_b: AgentPubKey,
+    pub reason: String,
+}
+
+/// Input for sign_link_delegation
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LinkDelegationInput {
+    pub agent_a: AgentPubKey,
+    pub agent_b: AgentPubKey,
+    /// Agent allowed to publish the link
+    pub delegate: AgentPubKey,
+}
+
+/// The two agents are_agents_linked checks, in either order
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AgentPair {
+    pub agent_a: AgentPubKey,
+    pub agent_b: AgentPubKey,
+}
+
+/// Input for create_direct_link (API-mediated linking for desktop apps)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectLinkInput {
+    /// The other agent's public key (e.g., the desktop agent)
+    pub other_agent: AgentPubKey,
+    /// The other agent's Ed25519 signature over the sorted key pair bytes
+    pub other_signature: Signature,
+}
+
+/// A link was created or revoked. Emitted to this agent's clients and sent
+/// to the other agent in the link, which re-emits it to its own.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum AgentLinkingSignal {
+    LinkCreated {
+        entry_action_hash: ActionHash,
+        agent_a: AgentPubKey,
+        agent_b: AgentPubKey,
+    },
+    LinkRevoked {
+        entry_action_hash: ActionHash,
+        agent_a: AgentPubKey,
+        agent_b: AgentPubKey,
+    },
+}
+
+impl AgentLinkingSignal {
+    /// The two agents in the link
+    pub fn agents(&self) -> (&AgentPubKey, &AgentPubKey) {
+        match self {
+            AgentLinkingSignal::LinkCreated { agent_a, agent_b, .. }
+            | AgentLinkingSignal::LinkRevoked { agent_a, agent_b, .. } => (agent_a, agent_b),
+        }
+    }
+}
+
+#[hdk_entry_types]
+#[unit_enum(UnitEntryTypes)]
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum EntryTypes {
+    IsSamePerson(IsSamePersonEntry),
+}
+
+#[derive(Serialize, Deserialize)]
+#[hdk_link_types]
+pub enum LinkTypes {
+    /// Links from an agent's pubkey to IsSamePersonEntry actions for lookup
+    AgentToIsSamePerson,
+    /// Links from a target agent's pubkey to a pending link request (async ceremony)
+    PendingLinkRequest,
+}
+
+/// Sort two agent keys deterministically and concatenate their raw bytes.
+/// Both agents sign this same payload to produce their signatures.
+/// Uses raw 39-byte representation (3-byte prefix + 32-byte key + 4-byte checksum)
+/// for each key, sorted lexicographically.
+pub fn sorted_agent_pair_bytes(
+    agent_a: &AgentPubKey,
+    agent_b: &AgentPubKey,
+) -> ExternResult<Vec<u8>> {
+    let mut keys = vec![agent_a.clone(), agent_b.clone()];
+    keys.sort();
+
+    let mut payload = Vec::with_capacity(78); // 39 bytes per key × 2
+    for key in &keys {
+        payload.extend_from_slice(key.get_raw_39());
+    }
+
+    Ok(payload)
+}
+
+/// What one of the linked agents signs to let `delegate` publish the link:
+/// a domain tag, the sorted pair bytes, then the delegate's raw 39 bytes
+pub fn link_delegation_payload(
+    agent_a: &AgentPubKey,
+    agent_b: &AgentPubKey,
+    delegate: &AgentPubKey,
+) -> ExternResult<Vec<u8>> {
+    let mut payload = b"is-same-person-delegation-v1:".to_vec();
+    payload.extend_from_slice(&sorted_agent_pair_bytes(agent_a, agent_b)?);
+    payload.extend_from_slice(delegate.get_raw_39());
+    Ok(payload)
+}
+
+#[cfg_attr(not(feature = "integrity"), hdk_extern)]
+pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
+    match op.flattened::<EntryTypes, LinkTypes>()? {
+        FlatOp::StoreEntry(store_entry) => match store_entry {
+            OpEntry::CreateEntry {
+                app_entry, action, ..
+            } => match app_entry {
+                EntryTypes::IsSamePerson(entry) => {
+                    validate_is_same_person_entry(&entry, &action.author)
+                }
+            },
+            OpEntry::UpdateEntry {
+                app_entry, action, ..
+            } => match app_entry {
+                EntryTypes::IsSamePerson(entry) => {
+                    validate_is_same_person_entry(&entry, &action.author)
+                }
+            },
+            _ => Ok(ValidateCallbackResult::Valid),
+        },
+        FlatOp::RegisterCreateLink {
+            link_type, ..
+        } => match link_type {
+            LinkTypes::AgentToIsSamePerson => Ok(ValidateCallbackResult::Valid),
+            LinkTypes::PendingLinkRequest => Ok(ValidateCallbackResult::Valid),
+        },
+        FlatOp::RegisterDeleteLink {
+            link_type,
+            action,
+            original_action,
+            ..
+        } => match link_type {
+            LinkTypes::PendingLinkRequest => {
+                if action.author != original_action.author {
+                    return Ok(ValidateCallbackResult::Invalid(
+                        "Only the original author can delete a pending link request".to_string(),
+                    ));
+                }
+                Ok(ValidateCallbackResult::Valid)
+            }
+            LinkTypes::AgentToIsSamePerson => Ok(ValidateCallbackResult::Valid),
+        },
+        // Revocation via Delete is handled in the coordinator layer
+        // (non-deterministic — cannot check in validation).
+        _ => Ok(ValidateCallbackResult::Valid),
+    }
+}
+
+/// Validate an IsSamePersonEntry:
+/// 1. agent_a and agent_b must be different
+/// 2. agent_a must be lexicographically smaller than agent_b (canonical ordering)
+/// 3. Author must be one of the two agents, or hold a delegation signed by one
+/// 4. Both signatures must verify over the sorted key pair bytes
+fn validate_is_same_person_entry(
+    entry: &IsSamePersonEntry,
+    author: &AgentPubKey,
+) -> ExternResult<ValidateCallbackResult> {
+    if entry.agent_a == entry.agent_b {
+        return Ok(ValidateCallbackResult::Invalid(
+            "agent_a and agent_b must be different agents".to_string(),
+        ));
+    }
+
+    if entry.agent_a >= entry.agent_b {
+        return Ok(ValidateCallbackResult::Invalid(
+            "agent_a must be lexicographically smaller than agent_b".to_string(),
+        ));
+    }
+
+    if author != &entry.agent_a && author != &entry.agent_b {
+        let delegated = match &entry.delegation {
+            Some(delegation)
+                if delegation.signer == entry.agent_a || delegation.signer == entry.agent_b =>
+            {
+                verify_signature(
+                    delegation.signer.clone(),
+                    delegation.signature.clone(),
+                    link_delegation_payload(&entry.agent_a, &entry.agent_b, author)?,
+                )?
+            }
+            _ => false,
+        };
+        if !delegated {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Author must be one of the two agents in the entry, or delegated by one".to_string(),
+            ));
+        }
+    }
+
+    let payload = sorted_agent_pair_bytes(&entry.agent_a, &entry.agent_b)?;
+
+    if !verify_signature(entry.agent_a.clone(), entry.signature_a.clone(), payload.clone())? {
+        return Ok(ValidateCallbackResult::Invalid(
+            "signature_a does not verify against agent_a".to_string(),
+        ));
+    }
+
+    if !verify_signature(entry.agent_b.clone(), entry.signature_b.clone(), payload)? {
+        return Ok(ValidateCallbackResult::Invalid(
+            "signature_b does not verify against agent_b".to_string(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+#[cfg(test)]
+mod tests {
+    //! Agent keys are holo_hash encodings of the Ed25519 keys for seeds [1; 32]
+    //! (A) and [2; 32] (B); B's raw bytes sort first.
+
+    use super::*;
+
+    const AGENT_A: &str = concat!(
+        "8420248a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf3748801b4",
+        "0f6f5c85805260",
+    );
+    const AGENT_B: &str = concat!(
+        "8420248139770ea87d175f56a35466c34c7ecccb8d8a91b4ee37a25df60f5b8f",
+        "c9b39423966a95",
+    );
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn agent_key(hex: &str) -> AgentPubKey {
+        AgentPubKey::from_raw_39(bytes(hex))
+    }
+
+    #[test]
+    fn sorts_agent_pairs() {
+        let (a, b) = (agent_key(AGENT_A), agent_key(AGENT_B));
+        let expected = [bytes(AGENT_B), bytes(AGENT_A)].concat();
+
+        assert_eq!(sorted_agent_pair_bytes(&a, &b).unwrap(), expected);
+        assert_eq!(sorted_agent_pair_bytes(&b, &a).unwrap(), expected);
+    }
+
+    #[test]
+    fn builds_delegation_payloads() {
+        let (a, b) = (agent_key(AGENT_A), agent_key(AGENT_B));
+        let expected = [
+            b"is-same-person-delegation-v1:".to_vec(),
+            bytes(AGENT_B),
+            bytes(AGENT_A),
+            bytes(AGENT_A),
+        ]
+        .concat();
+
+        assert_eq!(link_delegation_payload(&a, &b, &a).unwrap(), expected);
+        assert_eq!(link_delegation_payload(&b, &a, &a).unwrap(), expected);
+        // Naming another delegate changes the payload
+        assert_ne!(link_delegation_payload(&a, &b, &b).unwrap(), expected);
+    }
+}
@@ -0,0 +1,133 @@
+//! Error kinds shared by the identity zomes and their clients.
+//!
+//! Each kind has a stable code. A zome returns one with
+//! `wasm_error!(IdentityError::ProfileNotFound(...))`, which travels as the
+//! guest error string `[PROFILE_NOT_FOUND] No profile found to update`.
+//! Clients branch on the code (see `IdentityError::parse`); the message is
+//! for humans and may change. Codes never change meaning once published.
+//!
+//! The conductor hands the guest string to clients debug-printed inside its
+//! own error text, possibly more than once. So the message on the wire never
+//! holds a character that debug-printing escapes: `"` is sent as `'`, `\` as
+//! `/` and other escaped characters as a space. The text then survives any
+//! number of layers unchanged, and the message ends at the first `"` or `\`
+//! after the `[CODE] ` prefix (or at the end of the string).
+
+use std::fmt;
+
+use hdi::prelude::*;
+
+/// Defines `IdentityError` from a single table of variants and their codes
+macro_rules! identity_errors {
+    ($($(#[$doc:meta])* $variant:ident => $code:literal,)*) => {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum IdentityError {
+            $($(#[$doc])* $variant(String),)*
+        }
+
+        impl IdentityError {
+            /// Build an error from its code and message; None for an unknown code
+            pub fn from_code(code: &str, message: String) -> Option<Self> {
+                match code {
+                    $($code => Some(IdentityError::$variant(message)),)*
+                    _ => None,
+                }
+            }
+
+            /// The stable code, e.g. "PROFILE_NOT_FOUND"
+            pub fn code(&self) -> &'static str {
+                match self {
+                    $(IdentityError::$variant(_) => $code,)*
+                }
+            }
+
+            pub fn message(&self) -> &str {
+                match self {
+                    $(IdentityError::$variant(message))|* => message,
+                }
+            }
+        }
+    };
+}
+
+identity_errors! {
+    /// Input fails a precondition (missing DID, past expiry, self-link, ...)
+    InvalidInput => "INVALID_INPUT",
+    /// A site ID can't be canonicalized
+    InvalidSiteId => "INVALID_SITE_ID",
+    /// The agent has no profile
+    ProfileNotFound => "PROFILE_NOT_FOUND",
+    /// The agent already has a profile
+    ProfileExists => "PROFILE_EXISTS",
+    /// A referenced record doesn't exist (or hasn't reached this agent yet)
+    NotFound => "NOT_FOUND",
+    /// A record holds no entry, or not the expected entry type
+    WrongEntryType => "WRONG_ENTRY_TYPE",
+    /// The caller isn't allowed to do this (not an owner, not the granting agent, ...)
+    NotAuthorized => "NOT_AUTHORIZED",
+    /// A signature doesn't verify against its agent
+    InvalidSignature => "INVALID_SIGNATURE",
+    SiteNotRegistered => "SITE_NOT_REGISTERED",
+    SiteAlreadyRegistered => "SITE_ALREADY_REGISTERED",
+    NotAMember => "NOT_A_MEMBER",
+    /// An invite can't be redeemed (revoked, expired, used up, wrong agent or code)
+    InviteRejected => "INVITE_REJECTED",
+    /// State changed underneath the call; retry from the start
+    Conflict => "CONFLICT",
+    /// An identity export can't be imported here
+    ExportRejected => "EXPORT_REJECTED",
+    /// A call to another zome or cell failed
+    CallFailed => "CALL_FAILED",
+    Serialization => "SERIALIZATION",
+    /// Something that should not happen (e.g. a record just written can't be read back)
+    Internal => "INTERNAL",
+}
+
+impl IdentityError {
+    /// Find the IdentityError in an error string as a client sees it: the
+    /// guest string itself, or conductor error text embedding it.
+    ///
+    /// Looks for the first `[CODE] ` with a known code; the message is what
+    /// follows, up to the first `"` or `\` (see the crate docs).
+    pub fn parse(error: &str) -> Option<Self> {
+        let mut rest = error;
+        while let Some(start) = rest.find('[') {
+            rest = &rest[start + 1..];
+            let Some((code, tail)) = rest.split_once("] ") else {
+                continue;
+            };
+            let end = tail.find(['"', '\\']).unwrap_or(tail.len());
+            if let Some(error) = IdentityError::from_code(code, tail[..end].to_string()) {
+                return Some(error);
+            }
+        }
+        None
+    }
+}
+
+/// The character sent in place of `c`, so debug-printing never escapes the message
+fn wire_char(c: char) -> char {
+    match c {
+        '"' | '\'' => '\'',
+        '\\' => '/',
+        // Printed as is by `str`'s Debug (`'` is the only char escaped on its own)
+        c if c.escape_debug().count() == 1 => c,
+        _ => ' ',
+    }
+}
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message: String = self.message().chars().map(wire_char).collect();
+        write!(f, "[{}] {}", self.code(), message)
+    }
+}
+
+impl std::error::Error for IdentityError {}
+
+/// Lets zomes write `wasm_error!(IdentityError::...)`, keeping the caller's file and line
+impl From<IdentityError> for WasmErrorInner {
+    fn from(error: IdentityError) -> Self {
+        WasmErrorInner::Guest(error.to_string())
+    }
+}
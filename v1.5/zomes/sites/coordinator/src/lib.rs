@@ -0,0 +1,2220 @@
+use ed25519_dalek::{Signer, SigningKey};
+use hdk::prelude::*;
+use sites_integrity::*;
+use identity_errors::IdentityError;
+
+#[hdk_dependent_entry_types]
+enum EntryZomes {
+    IntegritySites(sites_integrity::EntryTypes),
+}
+
+// ── Input/Output Types ──────────────────────────────────────────────
+
+/// Input for register_site
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RegisterSiteInput {
+    pub site_id: String,
+    /// Only members holding a valid invite count as members
+    #[serde(default)]
+    pub invite_only: bool,
+    /// How long memberships last (seconds) unless renewed; None: they don't expire
+    #[serde(default)]
+    pub membership_ttl: Option<i64>,
+    /// Issued by a registration attestor once the caller proved domain control
+    pub attestation: RegistrationAttestation,
+}
+
+/// Input for create_invite. Give exactly one of `invitee` or `code`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreateInviteInput {
+    pub site_id: String,
+    #[serde(default)]
+    pub invitee: Option<AgentPubKey>,
+    /// Redeemable code chosen by the site; only a public key derived from it is stored
+    #[serde(default)]
+    pub code: Option<String>,
+    pub max_uses: u32,
+    pub expires_at: Option<i64>,
+    /// How long memberships redeeming the invite last (seconds); None: the site's default
+    #[serde(default)]
+    pub membership_ttl: Option<i64>,
+}
+
+/// Input for join_site_with_invite
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JoinWithInviteInput {
+    pub invite_hash: ActionHash,
+    /// Required for code invites
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// Input for site queries: either a bare site ID or a site ID plus blinding key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum SiteQueryInput {
+    SiteId(String),
+    Options(SiteQuery),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SiteQuery {
+    pub site_id: String,
+    /// Required to find memberships made in blinded mode
+    #[serde(default)]
+    pub blinding_key: Option<Vec<u8>>,
+}
+
+impl SiteQueryInput {
+    fn into_query(self) -> SiteQuery {
+        match self {
+            SiteQueryInput::SiteId(site_id) => SiteQuery {
+                site_id,
+                blinding_key: None,
+            },
+            SiteQueryInput::Options(query) => query,
+        }
+    }
+}
+
+/// Input for is_agent_site_member
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AgentSiteQuery {
+    pub agent: AgentPubKey,
+    pub site_id: String,
+    /// Required to match memberships the agent made in blinded mode
+    #[serde(default)]
+    pub blinding_key: Option<Vec<u8>>,
+}
+
+/// Input for sign_as_pairwise
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PairwiseSignInput {
+    pub site_id: String,
+    pub data: Vec<u8>,
+}
+
+/// Input for verify_pairwise_signature
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PairwiseSignatureInput {
+    pub site_id: String,
+    pub pairwise_id: String,
+    pub data: Vec<u8>,
+    pub signature: Signature,
+}
+
+/// Input for set_membership_metadata
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetMetadataInput {
+    /// Action hash of the caller's SiteMembership
+    pub membership_hash: ActionHash,
+    pub version: u32,
+    pub data: String,
+}
+
+/// Input for renew_membership
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RenewMembershipInput {
+    pub membership_hash: ActionHash,
+    /// New end of the validity window (seconds)
+    pub expires_at: i64,
+}
+
+/// The window in which a membership counts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MembershipValidity {
+    /// When the member joined (seconds)
+    pub valid_from: i64,
+    /// Latest expiry across the membership and its renewals; None never expires
+    pub valid_until: Option<i64>,
+    /// Not expired right now
+    pub active: bool,
+}
+
+/// Input for prepare_ownership_transfer
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferOwnershipInput {
+    pub site_id: String,
+    /// New owner set
+    pub owners: Vec<AgentPubKey>,
+    /// Owners needed for admin actions and the next transfer (1 = any one owner)
+    pub threshold: u32,
+}
+
+/// Input for issue_membership_credential
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IssueCredentialInput {
+    pub site_id: String,
+    pub member: AgentPubKey,
+    /// Defaults to DEFAULT_CREDENTIAL_TTL_SECONDS after issue
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+/// Result of verify_membership_credential
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CredentialVerification {
+    pub valid: bool,
+    /// Why the credential was rejected
+    pub reason: Option<String>,
+    /// The decoded credential, if the bytes could be decoded
+    pub credential: Option<MembershipCredential>,
+}
+
+/// Result of is_site_member_for_identity
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdentityMembership {
+    pub is_member: bool,
+    /// The agent (the caller or one linked to it) holding the membership
+    pub agent: Option<AgentPubKey>,
+}
+
+/// The part of the users zome's LatestUserProfile a credential needs
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+struct ProfileDid {
+    did: String,
+}
+
+/// Input for grant_consent
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GrantConsentInput {
+    pub site_id: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<i64>,
+}
+
+/// A consent record together with its current status
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConsentOutput {
+    pub consent_hash: ActionHash,
+    pub consent: SiteConsent,
+    /// Timestamp of the revoking Delete, if the consent was revoked
+    pub revoked_at: Option<Timestamp>,
+    /// Not revoked and not expired
+    pub active: bool,
+}
+
+/// Input for grant_role
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GrantRoleInput {
+    pub site_id: String,
+    pub agent: AgentPubKey,
+    pub role: String,
+}
+
+/// Input for get_member_roles
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MemberRolesInput {
+    pub site_id: String,
+    pub agent: AgentPubKey,
+}
+
+/// Position in a site's member listing: the last membership link returned.
+/// Listings are ordered by (bucket, timestamp, create_link_hash) and resume
+/// strictly after the cursor, so deleted links never shift later pages.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MembersCursor {
+    /// Day bucket holding the link
+    pub bucket: i64,
+    pub timestamp: Timestamp,
+    pub create_link_hash: ActionHash,
+}
+
+/// Input for get_site_members_page
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MembersPageInput {
+    pub site_id: String,
+    #[serde(default)]
+    pub blinding_key: Option<Vec<u8>>,
+    /// None to start from the earliest members
+    pub cursor: Option<MembersCursor>,
+    /// Page size, capped at MAX_MEMBERS_PAGE_SIZE
+    pub limit: u32,
+}
+
+/// A member entry in a paginated listing
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SiteMember {
+    pub agent: AgentPubKey,
+    pub membership_hash: ActionHash,
+    pub joined_at: Timestamp,
+    /// End of the membership's current validity window, if it expires
+    pub valid_until: Option<i64>,
+}
+
+/// One page of site members, oldest first
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MembersPage {
+    pub members: Vec<SiteMember>,
+    /// Pass back to get the next page; None when the listing is exhausted
+    pub next_cursor: Option<MembersCursor>,
+}
+
+/// What import_memberships did with each site
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MembershipsImport {
+    /// Memberships created
+    pub imported: Vec<Record>,
+    /// Sites that couldn't be joined, with the reason
+    pub skipped: Vec<SkippedMembership>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkippedMembership {
+    /// As exported
+    pub site_id: String,
+    pub reason: String,
+}
+
+// ── Helpers ─────────────────────────────────────────────────────────
+
+const MAX_MEMBERS_PAGE_SIZE: u32 = 100;
+
+const DEFAULT_CREDENTIAL_TTL_SECONDS: i64 = 30 * 86_400;
+
+/// Agent keys (`uhCAk...`, comma-separated) whose registration attestations
+/// count: the Auth API's signing key. Set from `FLOWSTA_REGISTRATION_ATTESTORS`
+/// when the zome is built (see build.sh). Kept out of the DNA properties so
+/// rotating the key is a coordinator update rather than a new network.
+const REGISTRATION_ATTESTORS: Option<&str> = option_env!("FLOWSTA_REGISTRATION_ATTESTORS");
+
+/// Whether this zome trusts an attestor's registration attestations
+fn is_registration_attestor(attestor: &AgentPubKey) -> bool {
+    let attestor = attestor.to_string();
+    REGISTRATION_ATTESTORS
+        .unwrap_or_default()
+        .split(',')
+        .any(|key| key.trim() == attestor)
+}
+
+/// Canonical form of a caller-supplied site ID (INVALID_SITE_ID if it has none)
+fn parse_site_id(site_id: &str) -> ExternResult<String> {
+    canonicalize_site_id(site_id).map_err(|e| wasm_error!(IdentityError::InvalidSiteId(e)))
+}
+
+/// Anchor that all memberships of a site are linked from (keyed by canonical or blinded ID)
+fn site_anchor(index_id: &str) -> ExternResult<EntryHash> {
+    Path::from(vec![Component::from("sites"), Component::from(index_id)]).path_entry_hash()
+}
+
+/// Public SiteMembership records of an agent (authored by that agent).
+/// Pairwise memberships are private and never appear here.
+fn agent_memberships(agent: AgentPubKey) -> ExternResult<Vec<Record>> {
+    let links = get_links(
+        LinkQuery::try_new(agent.clone(), LinkTypes::AgentToSiteMemberships)?,
+        GetStrategy::default()
+    )?;
+
+    let mut memberships = Vec::new();
+    for link in links {
+        if let Some(action_hash) = link.target.into_action_hash() {
+            if let Some(record) = get(action_hash, GetOptions::default())? {
+                if record.action().author() == &agent {
+                    memberships.push(record);
+                }
+            }
+        }
+    }
+
+    Ok(memberships)
+}
+
+/// What decides whether a site's memberships count, read once per query
+/// from the site's current registration
+struct SiteRules {
+    invite_only: bool,
+    /// Default membership lifetime (seconds)
+    membership_ttl: Option<i64>,
+    /// Renewals only count when issued by these owners
+    owners: Option<SiteOwners>,
+}
+
+/// The rules for a (canonical) site; unregistered and blinded IDs have none
+fn site_rules(site_id: &str) -> ExternResult<SiteRules> {
+    let registration = match current_site_registration(site_id)? {
+        Some(record) => match record.entry().to_app_option::<SiteRegistration>() {
+            Ok(Some(registration)) => Some((registration, record)),
+            _ => None,
+        },
+        None => None,
+    };
+    let Some((registration, record)) = registration else {
+        return Ok(SiteRules {
+            invite_only: false,
+            membership_ttl: None,
+            owners: None,
+        });
+    };
+
+    Ok(SiteRules {
+        invite_only: registration.invite_only,
+        membership_ttl: registration.membership_ttl,
+        owners: Some(site_owners(&record)?),
+    })
+}
+
+/// The current validity window of a membership: its own expires_at, pushed
+/// out by non-revoked renewals that count under the site's current owners
+fn membership_validity(
+    membership_hash: &ActionHash,
+    membership: &SiteMembership,
+    rules: &SiteRules,
+) -> ExternResult<MembershipValidity> {
+    let mut valid_until = membership.expires_at;
+
+    if let (Some(until), Some(owners)) = (valid_until.as_mut(), &rules.owners) {
+        let links = get_links(
+            LinkQuery::try_new(membership_hash.clone(), LinkTypes::MembershipToRenewals)?,
+            GetStrategy::default()
+        )?;
+        for link in links {
+            let Some(renewal_hash) = link.target.into_action_hash() else {
+                continue;
+            };
+            let Some(Details::Record(record_details)) = get_details(renewal_hash, GetOptions::default())? else {
+                continue;
+            };
+            // Revoked renewals are deleted
+            if !record_details.deletes.is_empty() {
+                continue;
+            }
+            if let Ok(Some(renewal)) = record_details.record.entry().to_app_option::<MembershipRenewal>() {
+                if &renewal.membership == membership_hash
+                    && renewal.registration == owners.registration
+                    && admin_action_counts(&record_details.record, owners)?
+                {
+                    *until = (*until).max(renewal.expires_at);
+                }
+            }
+        }
+    }
+
+    let now = sys_time()?.as_seconds_and_nanos().0;
+
+    Ok(MembershipValidity {
+        valid_from: membership.joined_at,
+        valid_until,
+        active: valid_until.map_or(true, |until| now < until),
+    })
+}
+
+/// The validity window of a membership record, if it is admitted to the site
+/// indexed under `index_id` (canonical, or blinded with the caller's key):
+/// the membership must be stored under that same ID, and on invite-only
+/// sites it must redeem an invite that still admits it
+fn admitted_membership(
+    record: &Record,
+    index_id: &str,
+    rules: &SiteRules,
+) -> ExternResult<Option<MembershipValidity>> {
+    let Ok(Some(membership)) = record.entry().to_app_option::<SiteMembership>() else {
+        return Ok(None);
+    };
+    // A blinded ID only proves which site was meant if it was derived with this site's key
+    if membership.site_id != index_id {
+        return Ok(None);
+    }
+    if rules.invite_only {
+        let (Some(invite_hash), Some(owners)) = (&membership.invite, &rules.owners) else {
+            return Ok(None);
+        };
+        if !invite_admits(invite_hash, record, owners)? {
+            return Ok(None);
+        }
+    }
+    // Only memberships naming the registration were held to its membership_ttl
+    if rules.membership_ttl.is_some()
+        && membership.registration.as_ref() != rules.owners.as_ref().map(|owners| &owners.registration)
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(membership_validity(record.action_address(), &membership, rules)?))
+}
+
+/// Key derived from a redeemable invite code (see `SiteInvite.code_key`)
+fn invite_code_key(code: &str) -> ExternResult<SigningKey> {
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&hash_blake2b(invite_code_seed_payload(code), 32)?);
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Memberships linked as redemptions of an invite, in redemption order
+/// (link timestamp, then hash)
+fn invite_redemptions(invite_hash: &ActionHash) -> ExternResult<Vec<ActionHash>> {
+    let mut links = get_links(
+        LinkQuery::try_new(invite_hash.clone(), LinkTypes::InviteToRedemptions)?,
+        GetStrategy::default()
+    )?;
+    links.sort_by(|a, b| {
+        (a.timestamp, &a.create_link_hash).cmp(&(b.timestamp, &b.create_link_hash))
+    });
+
+    let mut redemptions = Vec::new();
+    for link in links {
+        if let Some(membership_hash) = link.target.into_action_hash() {
+            if !redemptions.contains(&membership_hash) {
+                redemptions.push(membership_hash);
+            }
+        }
+    }
+
+    Ok(redemptions)
+}
+
+/// Whether an invite admits a membership record: issued under the site's
+/// current registration by its current owners, redeemed before it expired
+/// or was revoked, and among its first `max_uses` redemptions
+fn invite_admits(
+    invite_hash: &ActionHash,
+    membership_record: &Record,
+    owners: &SiteOwners,
+) -> ExternResult<bool> {
+    let Some(Details::Record(invite_details)) = get_details(invite_hash.clone(), GetOptions::default())? else {
+        return Ok(false);
+    };
+    let Ok(Some(invite)) = invite_details.record.entry().to_app_option::<SiteInvite>() else {
+        return Ok(false);
+    };
+
+    if invite.registration != owners.registration
+        || !admin_action_counts(&invite_details.record, owners)?
+    {
+        return Ok(false);
+    }
+
+    let joined_at = membership_record.action().timestamp();
+    if invite
+        .expires_at
+        .is_some_and(|expires_at| joined_at.as_seconds_and_nanos().0 >= expires_at)
+    {
+        return Ok(false);
+    }
+
+    // Revoking stops later redemptions; earlier ones keep counting
+    if invite_details
+        .deletes
+        .iter()
+        .any(|delete| delete.action().timestamp() <= joined_at)
+    {
+        return Ok(false);
+    }
+
+    Ok(invite_redemptions(invite_hash)?
+        .iter()
+        .take(invite.max_uses as usize)
+        .any(|membership_hash| membership_hash == membership_record.action_address()))
+}
+
+/// Whether any of the membership records is an active, admitted membership
+/// for the given canonical or blinded site ID
+fn has_membership(records: &[Record], index_id: &str, rules: &SiteRules) -> ExternResult<bool> {
+    for record in records {
+        if admitted_membership(record, index_id, rules)?.is_some_and(|validity| validity.active) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// The member and validity window behind a site member link, if the link's
+/// author wrote a membership of this site that currently counts (admitted
+/// and not expired). The membership's author is the member.
+fn active_member_link(
+    link: &Link,
+    index_id: &str,
+    rules: &SiteRules,
+) -> ExternResult<Option<(AgentPubKey, MembershipValidity)>> {
+    let Some(membership_hash) = link.target.clone().into_action_hash() else {
+        return Ok(None);
+    };
+    let Some(record) = get(membership_hash, GetOptions::default())? else {
+        return Ok(None);
+    };
+    let member = record.action().author().clone();
+    if member != link.author {
+        return Ok(None);
+    }
+
+    Ok(admitted_membership(&record, index_id, rules)?
+        .filter(|validity| validity.active)
+        .map(|validity| (member, validity)))
+}
+
+/// The current owners of a site, which must include the caller
+fn my_site_owners(site_id: &str) -> ExternResult<SiteOwners> {
+    let owners = current_site_owners(site_id)?
+        .ok_or(wasm_error!(IdentityError::SiteNotRegistered(
+            format!("Site '{}' is not registered", site_id)
+        )))?;
+
+    if !owners.owners.contains(&agent_info()?.agent_initial_pubkey) {
+        return Err(wasm_error!(IdentityError::NotAuthorized(
+            "Only a site owner can do this".into()
+        )));
+    }
+
+    Ok(owners)
+}
+
+/// The current owners of a (canonical) site, if it is registered
+fn current_site_owners(site_id: &str) -> ExternResult<Option<SiteOwners>> {
+    match current_site_registration(site_id)? {
+        Some(record) => Ok(Some(site_owners(&record)?)),
+        None => Ok(None),
+    }
+}
+
+/// Follow a registration's ownership chain to its current owner set.
+/// If two transfers extend the same owner set, the earliest one wins
+/// (ties broken by action hash), like registrations.
+fn site_owners(registration_record: &Record) -> ExternResult<SiteOwners> {
+    let registration: SiteRegistration = registration_record
+        .entry()
+        .to_app_option()
+        .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+            "Could not deserialize site registration".into()
+        )))?
+        .ok_or(wasm_error!(IdentityError::WrongEntryType(
+            "Site registration entry is None".into()
+        )))?;
+    let registration_hash = registration_record.action_address().clone();
+
+    let links = get_links(
+        LinkQuery::try_new(registration_hash.clone(), LinkTypes::RegistrationToTransfers)?,
+        GetStrategy::default()
+    )?;
+
+    let mut transfers: Vec<(Record, SiteOwnershipTransfer)> = Vec::new();
+    for link in links {
+        let Some(transfer_hash) = link.target.into_action_hash() else {
+            continue;
+        };
+        let Some(record) = get(transfer_hash, GetOptions::default())? else {
+            continue;
+        };
+        if let Ok(Some(transfer)) = record.entry().to_app_option::<SiteOwnershipTransfer>() {
+            if transfer.registration == registration_hash {
+                transfers.push((record, transfer));
+            }
+        }
+    }
+    transfers.sort_by(|(a, _), (b, _)| {
+        (a.action().timestamp(), a.action_address()).cmp(&(b.action().timestamp(), b.action_address()))
+    });
+
+    let mut owners = SiteOwners {
+        registration: registration_hash,
+        ownership: None,
+        owners: vec![registration.owner],
+        threshold: 1,
+    };
+    while let Some((record, transfer)) = transfers
+        .iter()
+        .find(|(_, transfer)| transfer.previous == owners.ownership)
+    {
+        owners = SiteOwners {
+            registration: owners.registration,
+            ownership: Some(record.action_address().clone()),
+            owners: transfer.owners.clone(),
+            threshold: transfer.threshold,
+        };
+    }
+
+    Ok(owners)
+}
+
+/// Whether an admin action (role grant, invite or renewal) counts under the
+/// current owner set: its author is a current owner and, with a threshold
+/// above 1, enough other current owners have approved it
+fn admin_action_counts(record: &Record, owners: &SiteOwners) -> ExternResult<bool> {
+    let author = record.action().author();
+    if !owners.owners.contains(author) {
+        return Ok(false);
+    }
+    if owners.threshold <= 1 {
+        return Ok(true);
+    }
+
+    let links = get_links(
+        LinkQuery::try_new(record.action_address().clone(), LinkTypes::AdminActionToApprovals)?,
+        GetStrategy::default()
+    )?;
+
+    // The link author is the approver (checked in validation)
+    let mut approvers = vec![author.clone()];
+    for link in links {
+        if owners.owners.contains(&link.author) && !approvers.contains(&link.author) {
+            approvers.push(link.author);
+        }
+    }
+
+    Ok(approvers.len() >= owners.threshold as usize)
+}
+
+/// All day buckets that hold members of a site, in ascending order
+fn member_buckets(index_id: &str) -> ExternResult<Vec<i64>> {
+    let links = get_links(
+        LinkQuery::try_new(site_anchor(index_id)?, LinkTypes::SiteToMemberBuckets)?,
+        GetStrategy::default()
+    )?;
+
+    let mut buckets: Vec<i64> = links
+        .into_iter()
+        .filter_map(|link| bucket_from_tag(&link.tag))
+        .collect();
+    buckets.sort_unstable();
+    buckets.dedup();
+
+    Ok(buckets)
+}
+
+/// Membership links in one bucket, in a stable order (link timestamp, then hash)
+fn bucket_member_links(index_id: &str, bucket: i64) -> ExternResult<Vec<Link>> {
+    let mut links = get_links(
+        LinkQuery::try_new(member_bucket_anchor(index_id, bucket)?, LinkTypes::SiteToMembers)?,
+        GetStrategy::default()
+    )?;
+    links.sort_by(|a, b| {
+        (a.timestamp, &a.create_link_hash).cmp(&(b.timestamp, &b.create_link_hash))
+    });
+
+    Ok(links)
+}
+
+fn bucket_from_tag(tag: &LinkTag) -> Option<i64> {
+    let bytes: [u8; 8] = tag.0.as_slice().try_into().ok()?;
+    Some(i64::from_be_bytes(bytes))
+}
+
+/// The registration that counts for a canonical site ID: among those attested
+/// by a trusted attestor (see `REGISTRATION_ATTESTORS`), the one with the
+/// latest attestation, i.e. the most recent proof of domain control (ties
+/// broken by action hash so every agent picks the same record).
+/// Action timestamps are self-authored, so they play no part.
+fn current_site_registration(site_id: &str) -> ExternResult<Option<Record>> {
+    let links = get_links(
+        LinkQuery::try_new(site_anchor(site_id)?, LinkTypes::SiteToRegistration)?,
+        GetStrategy::default()
+    )?;
+
+    let mut current: Option<(SiteRegistration, Record)> = None;
+    for link in links {
+        let Some(action_hash) = link.target.into_action_hash() else {
+            continue;
+        };
+        let Some(record) = get(action_hash, GetOptions::default())? else {
+            continue;
+        };
+        let registration = match record.entry().to_app_option::<SiteRegistration>() {
+            Ok(Some(registration))
+                if registration.site_id == site_id
+                    && is_registration_attestor(&registration.attestation.attestor) =>
+            {
+                registration
+            }
+            _ => continue,
+        };
+
+        let attested_at = registration.attestation.attested_at;
+        let is_later = match &current {
+            Some((existing, existing_record)) => {
+                attested_at > existing.attestation.attested_at
+                    || (attested_at == existing.attestation.attested_at
+                        && record.action_address() < existing_record.action_address())
+            }
+            None => true,
+        };
+        if is_later {
+            current = Some((registration, record));
+        }
+    }
+
+    Ok(current.map(|(_, record)| record))
+}
+
+/// This agent's current pairwise memberships (private entries, so read from the source chain)
+fn my_pairwise_memberships() -> ExternResult<Vec<(Record, PairwiseSiteMembership)>> {
+    let left: Vec<ActionHash> = query(ChainQueryFilter::new().action_type(ActionType::Delete))?
+        .into_iter()
+        .filter_map(|record| match record.action() {
+            Action::Delete(delete) => Some(delete.deletes_address.clone()),
+            _ => None,
+        })
+        .collect();
+
+    Ok(chain_pairwise_memberships()?
+        .into_iter()
+        .filter(|(record, _)| !left.contains(record.action_address()))
+        .collect())
+}
+
+/// Every pairwise membership on this agent's chain, including ones it has left
+fn chain_pairwise_memberships() -> ExternResult<Vec<(Record, PairwiseSiteMembership)>> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(UnitEntryTypes::PairwiseSiteMembership.try_into()?)
+        .include_entries(true);
+
+    let mut memberships = Vec::new();
+    for record in query(filter)? {
+        if let Some(entry) = record.entry().as_option() {
+            if let Ok(membership) = PairwiseSiteMembership::try_from(entry) {
+                memberships.push((record, membership));
+            }
+        }
+    }
+
+    Ok(memberships)
+}
+
+/// Re-derive this agent's pairwise key for a canonical site ID.
+/// The seed is the hash of a deterministic signature that never leaves this call.
+fn my_pairwise_key(site_id: &str) -> ExternResult<SigningKey> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    let seed_signature = sign(my_agent_pub_key, pairwise_key_payload(site_id))?;
+
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&hash_blake2b(seed_signature.0.to_vec(), 32)?);
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Derive the pairwise ID and sign the binding payload with both keys
+fn my_pairwise_proof(site_id: &str) -> ExternResult<PairwiseProof> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    let pairwise_key = my_pairwise_key(site_id)?;
+    let pairwise_id = pairwise_id_from_key(pairwise_key.verifying_key().as_bytes());
+
+    let payload = pairwise_binding_payload(site_id, &pairwise_id, &my_agent_pub_key);
+    let proof = sign(my_agent_pub_key.clone(), payload.clone())?;
+    let key_proof = Signature(pairwise_key.sign(&payload).to_bytes());
+
+    Ok(PairwiseProof {
+        agent: my_agent_pub_key,
+        site_id: site_id.to_string(),
+        pairwise_id,
+        proof,
+        key_proof,
+    })
+}
+
+// ── Public Functions ────────────────────────────────────────────────
+
+/// Join a site - creates an immutable membership record
+/// The site_id is canonicalized first, so `https://Example.com/` and `example.com` are the same site
+/// With `pairwise: true` the membership is recorded privately under a per-site pairwise ID
+/// With a `blinding_key` the membership is indexed under a blinded site ID
+#[hdk_extern]
+pub fn join_site(input: JoinSiteInput) -> ExternResult<Record> {
+    let options = input.into_options();
+
+    if options.pairwise {
+        if options.blinding_key.is_some() {
+            return Err(wasm_error!(IdentityError::InvalidInput(
+                "Pairwise memberships are private; a blinding_key is not used".into()
+            )));
+        }
+        let site_id = parse_site_id(&options.site_id)?;
+        return join_site_pairwise(&site_id);
+    }
+
+    let canonical_site_id = parse_site_id(&options.site_id)?;
+    join_public_site(&canonical_site_id, options.blinding_key.as_deref())
+}
+
+/// Join a site that doesn't need an invite with a public SiteMembership,
+/// under its canonical or blinded ID
+fn join_public_site(canonical_site_id: &str, blinding_key: Option<&[u8]>) -> ExternResult<Record> {
+    let rules = site_rules(canonical_site_id)?;
+    if rules.invite_only {
+        return Err(wasm_error!(IdentityError::InviteRejected(format!(
+            "Site '{}' is invite-only; use join_site_with_invite",
+            canonical_site_id
+        ))));
+    }
+
+    // Canonical site ID, or its blinded form - never the raw input
+    let site_id = site_index_id(canonical_site_id, blinding_key)?;
+
+    // Blinded memberships can't name the registration without revealing the site
+    let registration = match blinding_key {
+        Some(_) if rules.membership_ttl.is_some() => {
+            return Err(wasm_error!(IdentityError::InvalidInput(format!(
+                "Site '{}' sets a membership lifetime, which blinded memberships can't carry",
+                canonical_site_id
+            ))));
+        }
+        Some(_) => None,
+        None => rules.owners.map(|owners| owners.registration),
+    };
+
+    create_site_membership(site_id, registration, None, None, rules.membership_ttl)
+}
+
+/// Re-create public memberships carried over from a previous DNA version
+/// (see users.import_data), given their site IDs. Each site is joined under
+/// its current rules on this DNA, so expiry restarts from the site's
+/// membership_ttl. Blinded IDs are kept as they are, since the blinding key
+/// isn't known here. Sites the caller is already a member of are passed over.
+///
+/// Sites that can't be joined this way (an invalid ID, or an invite-only
+/// site, which needs a new invite on this DNA) are skipped and reported;
+/// the rest are joined.
+#[hdk_extern]
+pub fn import_memberships(site_ids: Vec<String>) -> ExternResult<MembershipsImport> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    let mut joined: Vec<String> = agent_memberships(my_agent_pub_key)?
+        .iter()
+        .filter_map(|record| record.entry().to_app_option::<SiteMembership>().ok().flatten())
+        .map(|membership| membership.site_id)
+        .collect();
+
+    let mut report = MembershipsImport::default();
+    for exported_site_id in site_ids {
+        let blinded = exported_site_id.starts_with(BLINDED_SITE_ID_PREFIX);
+        let site_id = if blinded {
+            exported_site_id
+        } else {
+            match canonicalize_site_id(&exported_site_id) {
+                Ok(site_id) => site_id,
+                Err(reason) => {
+                    report.skipped.push(SkippedMembership {
+                        site_id: exported_site_id,
+                        reason,
+                    });
+                    continue;
+                }
+            }
+        };
+        if joined.contains(&site_id) {
+            continue;
+        }
+
+        let record = if blinded {
+            create_site_membership(site_id.clone(), None, None, None, None)?
+        } else {
+            if site_rules(&site_id)?.invite_only {
+                report.skipped.push(SkippedMembership {
+                    site_id,
+                    reason: "Invite-only site: join again with a new invite".to_string(),
+                });
+                continue;
+            }
+            join_public_site(&site_id, None)?
+        };
+        report.imported.push(record);
+        joined.push(site_id);
+    }
+
+    Ok(report)
+}
+
+/// Join a site by redeeming a SiteInvite (addressed to the caller, or by code).
+/// Required for invite-only sites.
+#[hdk_extern]
+pub fn join_site_with_invite(input: JoinWithInviteInput) -> ExternResult<Record> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+
+    let Some(Details::Record(invite_details)) =
+        get_details(input.invite_hash.clone(), GetOptions::default())?
+    else {
+        return Err(wasm_error!(IdentityError::NotFound("Invite not found".into())));
+    };
+    if !invite_details.deletes.is_empty() {
+        return Err(wasm_error!(IdentityError::InviteRejected("Invite has been revoked".into())));
+    }
+
+    let invite: SiteInvite = invite_details
+        .record
+        .entry()
+        .to_app_option()
+        .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+            "Could not deserialize invite".into()
+        )))?
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Entry is not a SiteInvite".into())))?;
+
+    if let Some(expires_at) = invite.expires_at {
+        if sys_time()?.as_seconds_and_nanos().0 >= expires_at {
+            return Err(wasm_error!(IdentityError::InviteRejected("Invite has expired".into())));
+        }
+    }
+
+    let rules = site_rules(&invite.site_id)?;
+    let issued_by_owners = match &rules.owners {
+        Some(owners) => {
+            invite.registration == owners.registration
+                && admin_action_counts(&invite_details.record, owners)?
+        }
+        None => false,
+    };
+    if !issued_by_owners {
+        return Err(wasm_error!(IdentityError::InviteRejected(
+            "Invite was not issued by the site's current owners".into()
+        )));
+    }
+
+    // Prove knowledge of the code by signing our own agent key with the code key,
+    // so the code never appears on the DHT
+    let invite_code_signature = match (&invite.invitee, &invite.code_key, &input.code) {
+        (Some(invitee), _, _) if invitee == &my_agent_pub_key => None,
+        (None, Some(code_key), Some(code)) => {
+            let key = invite_code_key(code)?;
+            if &agent_key_from_ed25519(key.verifying_key().as_bytes())? != code_key {
+                return Err(wasm_error!(IdentityError::InviteRejected(
+                    "The invite code is wrong".into()
+                )));
+            }
+            let payload = invite_redemption_payload(&input.invite_hash, &my_agent_pub_key);
+            Some(Signature(key.sign(&payload).to_bytes()))
+        }
+        _ => {
+            return Err(wasm_error!(IdentityError::InviteRejected(
+                "Invite is not addressed to this agent and no code was given".into()
+            )))
+        }
+    };
+
+    // Readers only admit the first max_uses redemptions; this spares a
+    // membership that would not count
+    if invite_redemptions(&input.invite_hash)?.len() >= invite.max_uses as usize {
+        return Err(wasm_error!(IdentityError::InviteRejected("Invite has no uses left".into())));
+    }
+
+    let record = create_site_membership(
+        invite.site_id,
+        Some(invite.registration),
+        Some(input.invite_hash.clone()),
+        invite_code_signature,
+        invite.membership_ttl.or(rules.membership_ttl),
+    )?;
+
+    create_link(
+        input.invite_hash,
+        record.action_address().clone(),
+        LinkTypes::InviteToRedemptions,
+        (),
+    )?;
+
+    Ok(record)
+}
+
+/// Create a public SiteMembership and its agent and site index links.
+/// `membership_ttl` comes from the invite or the site's registration.
+fn create_site_membership(
+    site_id: String,
+    registration: Option<ActionHash>,
+    invite: Option<ActionHash>,
+    invite_code_signature: Option<Signature>,
+    membership_ttl: Option<i64>,
+) -> ExternResult<Record> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    let now = sys_time()?;
+    let joined_at = now.as_seconds_and_nanos().0;
+
+    let membership = SiteMembership {
+        site_id: site_id.clone(),
+        joined_at,
+        agent_key: my_agent_pub_key.to_string(),
+        registration,
+        invite,
+        invite_code_signature,
+        expires_at: membership_ttl.map(|ttl| joined_at + ttl),
+    };
+
+    let membership_hash = create_entry(&EntryZomes::IntegritySites(EntryTypes::SiteMembership(membership.clone())))?;
+    create_link(
+        my_agent_pub_key.clone(),
+        membership_hash.clone(),
+        LinkTypes::AgentToSiteMemberships,
+        (),
+    )?;
+
+    // Site member queries read the site's day buckets
+    let bucket = member_bucket(membership.joined_at);
+    create_link(
+        member_bucket_anchor(&site_id, bucket)?,
+        membership_hash.clone(),
+        LinkTypes::SiteToMembers,
+        site_id.as_bytes().to_vec(),
+    )?;
+
+    // Index the bucket on the site anchor once, by the first member of the day
+    if !member_buckets(&site_id)?.contains(&bucket) {
+        create_link(
+            site_anchor(&site_id)?,
+            member_bucket_anchor(&site_id, bucket)?,
+            LinkTypes::SiteToMemberBuckets,
+            bucket.to_be_bytes().to_vec(),
+        )?;
+    }
+
+    let record = get(membership_hash, GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::Internal(
+            "Could not find the newly created membership".into()
+        )))?;
+
+    Ok(record)
+}
+
+/// Record a private PairwiseSiteMembership for a canonical site ID
+fn join_site_pairwise(site_id: &str) -> ExternResult<Record> {
+    let pairwise = my_pairwise_proof(site_id)?;
+
+    let membership = PairwiseSiteMembership {
+        site_id: pairwise.site_id,
+        pairwise_id: pairwise.pairwise_id,
+        proof: pairwise.proof,
+        key_proof: pairwise.key_proof,
+        joined_at: sys_time()?.as_seconds_and_nanos().0,
+    };
+
+    let membership_hash = create_entry(&EntryZomes::IntegritySites(
+        EntryTypes::PairwiseSiteMembership(membership),
+    ))?;
+
+    // Private entries aren't on the DHT, so read the record back from our own chain
+    my_pairwise_memberships()?
+        .into_iter()
+        .map(|(record, _)| record)
+        .find(|record| record.action_address() == &membership_hash)
+        .ok_or(wasm_error!(IdentityError::Internal(
+            "Could not find the newly created pairwise membership".into()
+        )))
+}
+
+/// Get all sites the current agent has joined
+#[hdk_extern]
+pub fn get_my_sites(_: ()) -> ExternResult<Vec<Record>> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    agent_memberships(my_agent_pub_key)
+}
+
+/// Get the public site memberships of any agent.
+/// Blinded memberships only show their blinded site ID; pairwise ones are never visible.
+#[hdk_extern]
+pub fn get_agent_sites(agent: AgentPubKey) -> ExternResult<Vec<Record>> {
+    agent_memberships(agent)
+}
+
+/// Leave a site by deleting one of the caller's memberships (a record from
+/// get_my_sites or get_my_pairwise_sites). Public memberships are also
+/// unlinked from the agent and the site, so member queries stop returning them.
+#[hdk_extern]
+pub fn leave_site(membership_hash: ActionHash) -> ExternResult<ActionHash> {
+    if my_pairwise_memberships()?
+        .iter()
+        .any(|(record, _)| record.action_address() == &membership_hash)
+    {
+        return delete_entry(membership_hash);
+    }
+
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    let record = get(membership_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Membership not found".into())))?;
+    if record.action().author() != &my_agent_pub_key {
+        return Err(wasm_error!(IdentityError::NotAuthorized(
+            "Only the member can leave with this membership".into()
+        )));
+    }
+    let membership = record
+        .entry()
+        .to_app_option::<SiteMembership>()
+        .ok()
+        .flatten()
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Entry is not a SiteMembership".into())))?;
+
+    let target = AnyLinkableHash::from(membership_hash.clone());
+    let bucket = member_bucket(membership.joined_at);
+    let links = get_links(
+        LinkQuery::try_new(my_agent_pub_key, LinkTypes::AgentToSiteMemberships)?,
+        GetStrategy::default()
+    )?
+    .into_iter()
+    .chain(bucket_member_links(&membership.site_id, bucket)?);
+    for link in links {
+        if link.target == target {
+            delete_link(link.create_link_hash, GetOptions::default())?;
+        }
+    }
+
+    delete_entry(membership_hash)
+}
+
+/// Check whether any agent is a member of a site (e.g. from a site's backend agent).
+/// Pass the site's blinding key to match memberships made in blinded mode.
+#[hdk_extern]
+pub fn is_agent_site_member(input: AgentSiteQuery) -> ExternResult<bool> {
+    let canonical_site_id = parse_site_id(&input.site_id)?;
+    let site_id = site_index_id(&canonical_site_id, input.blinding_key.as_deref())?;
+    has_membership(
+        &agent_memberships(input.agent)?,
+        &site_id,
+        &site_rules(&canonical_site_id)?,
+    )
+}
+
+/// Get all current members of a specific site (expired memberships are left out)
+/// Pass the site's blinding key to list members who joined in blinded mode
+#[hdk_extern]
+pub fn get_site_members(input: SiteQueryInput) -> ExternResult<Vec<AgentPubKey>> {
+    let query = input.into_query();
+    let canonical_site_id = parse_site_id(&query.site_id)?;
+    let site_id = site_index_id(&canonical_site_id, query.blinding_key.as_deref())?;
+    let rules = site_rules(&canonical_site_id)?;
+
+    let mut members: Vec<AgentPubKey> = Vec::new();
+    for bucket in member_buckets(&site_id)? {
+        for link in bucket_member_links(&site_id, bucket)? {
+            if members.contains(&link.author) {
+                continue;
+            }
+            if let Some((member, _)) = active_member_link(&link, &site_id, &rules)? {
+                members.push(member);
+            }
+        }
+    }
+
+    Ok(members)
+}
+
+/// Get one page of a site's current members, oldest first, with their validity windows.
+/// Only the buckets needed for the page are read, so large sites don't time out.
+#[hdk_extern]
+pub fn get_site_members_page(input: MembersPageInput) -> ExternResult<MembersPage> {
+    let canonical_site_id = parse_site_id(&input.site_id)?;
+    let site_id = site_index_id(&canonical_site_id, input.blinding_key.as_deref())?;
+    let rules = site_rules(&canonical_site_id)?;
+    let limit = input.limit.clamp(1, MAX_MEMBERS_PAGE_SIZE) as usize;
+    let start_bucket = input.cursor.as_ref().map_or(i64::MIN, |cursor| cursor.bucket);
+
+    let mut members = Vec::new();
+    let mut last: Option<MembersCursor> = None;
+    for bucket in member_buckets(&site_id)?.into_iter().filter(|b| *b >= start_bucket) {
+        for link in bucket_member_links(&site_id, bucket)? {
+            if let Some(cursor) = &input.cursor {
+                if (bucket, link.timestamp, &link.create_link_hash)
+                    <= (cursor.bucket, cursor.timestamp, &cursor.create_link_hash)
+                {
+                    continue;
+                }
+            }
+            if members.len() == limit {
+                return Ok(MembersPage {
+                    members,
+                    next_cursor: last,
+                });
+            }
+            last = Some(MembersCursor {
+                bucket,
+                timestamp: link.timestamp,
+                create_link_hash: link.create_link_hash.clone(),
+            });
+
+            let Some((member, validity)) = active_member_link(&link, &site_id, &rules)? else {
+                continue;
+            };
+            if let Some(membership_hash) = link.target.clone().into_action_hash() {
+                members.push(SiteMember {
+                    agent: member,
+                    membership_hash,
+                    joined_at: link.timestamp,
+                    valid_until: validity.valid_until,
+                });
+            }
+        }
+    }
+
+    Ok(MembersPage {
+        members,
+        next_cursor: None,
+    })
+}
+
+/// Approximate number of members of a site.
+/// Counts membership links per bucket without fetching them; an agent
+/// that joined more than once is counted more than once, expired
+/// memberships are counted, and on invite-only sites memberships without
+/// an invite are counted too.
+#[hdk_extern]
+pub fn count_site_members(input: SiteQueryInput) -> ExternResult<usize> {
+    let query = input.into_query();
+    let site_id = site_index_id(&query.site_id, query.blinding_key.as_deref())?;
+
+    let mut count = 0;
+    for bucket in member_buckets(&site_id)? {
+        count += count_links(LinkQuery::try_new(
+            member_bucket_anchor(&site_id, bucket)?,
+            LinkTypes::SiteToMembers,
+        )?)?;
+    }
+
+    Ok(count)
+}
+
+/// Register the current agent as the owner of a site.
+/// Needs an attestation of domain control from a registration attestor;
+/// the registration with the latest attestation wins.
+#[hdk_extern]
+pub fn register_site(input: RegisterSiteInput) -> ExternResult<Record> {
+    let site_id = parse_site_id(&input.site_id)?;
+
+    if !is_registration_attestor(&input.attestation.attestor) {
+        return Err(wasm_error!(IdentityError::NotAuthorized(format!(
+            "{} is not a registration attestor",
+            input.attestation.attestor
+        ))));
+    }
+
+    if let Some(current) = current_site_registration(&site_id)? {
+        if matches!(
+            current.entry().to_app_option::<SiteRegistration>(),
+            Ok(Some(registration))
+                if registration.attestation.attested_at >= input.attestation.attested_at
+        ) {
+            return Err(wasm_error!(IdentityError::SiteAlreadyRegistered(format!(
+                "Site '{}' is already registered under a newer attestation",
+                site_id
+            ))));
+        }
+    }
+
+    let registration = SiteRegistration {
+        site_id: site_id.clone(),
+        owner: agent_info()?.agent_initial_pubkey,
+        registered_at: sys_time()?.as_seconds_and_nanos().0,
+        invite_only: input.invite_only,
+        membership_ttl: input.membership_ttl,
+        attestation: input.attestation,
+    };
+
+    let registration_hash = create_entry(&EntryZomes::IntegritySites(
+        EntryTypes::SiteRegistration(registration),
+    ))?;
+
+    create_link(
+        site_anchor(&site_id)?,
+        registration_hash.clone(),
+        LinkTypes::SiteToRegistration,
+        site_id.as_bytes().to_vec(),
+    )?;
+
+    let record = get(registration_hash, GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::Internal(
+            "Could not find the newly created registration".into()
+        )))?;
+
+    Ok(record)
+}
+
+/// Get the registration (and so the owner) of a site, if it is registered
+#[hdk_extern]
+pub fn get_site_registration(site_id: String) -> ExternResult<Option<Record>> {
+    let site_id = parse_site_id(&site_id)?;
+    current_site_registration(&site_id)
+}
+
+/// Get the current owner set and threshold of a site, if it is registered
+#[hdk_extern]
+pub fn get_site_owners(site_id: String) -> ExternResult<Option<SiteOwners>> {
+    let site_id = parse_site_id(&site_id)?;
+    current_site_owners(&site_id)
+}
+
+/// Draft an ownership transfer from the site's current owners.
+/// Every party signs the draft with sign_ownership_transfer in their own
+/// cell, then one current owner submits it with transfer_site_ownership.
+#[hdk_extern]
+pub fn prepare_ownership_transfer(input: TransferOwnershipInput) -> ExternResult<SiteOwnershipTransfer> {
+    let site_id = parse_site_id(&input.site_id)?;
+    let owners = current_site_owners(&site_id)?
+        .ok_or(wasm_error!(IdentityError::SiteNotRegistered(
+            format!("Site '{}' is not registered", site_id)
+        )))?;
+
+    Ok(SiteOwnershipTransfer {
+        site_id,
+        registration: owners.registration,
+        previous: owners.ownership,
+        owners: input.owners,
+        threshold: input.threshold,
+        signatures: vec![],
+        transferred_at: sys_time()?.as_seconds_and_nanos().0,
+    })
+}
+
+/// Sign a drafted ownership transfer as the current agent (an outgoing or incoming owner)
+#[hdk_extern]
+pub fn sign_ownership_transfer(transfer: SiteOwnershipTransfer) -> ExternResult<OwnerSignature> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    let signature = sign(my_agent_pub_key.clone(), ownership_transfer_payload_bytes(&transfer))?;
+
+    Ok(OwnerSignature {
+        owner: my_agent_pub_key,
+        signature,
+    })
+}
+
+/// Submit a signed ownership transfer (current owners only).
+/// The caller's own signature is added if missing; validation checks the rest.
+#[hdk_extern]
+pub fn transfer_site_ownership(mut transfer: SiteOwnershipTransfer) -> ExternResult<ActionHash> {
+    let owners = my_site_owners(&transfer.site_id)?;
+    if transfer.registration != owners.registration || transfer.previous != owners.ownership {
+        return Err(wasm_error!(IdentityError::Conflict(
+            "Site ownership changed since this transfer was prepared; prepare it again".into()
+        )));
+    }
+
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    if !transfer.signatures.iter().any(|s| s.owner == my_agent_pub_key) {
+        let my_signature = sign_ownership_transfer(transfer.clone())?;
+        transfer.signatures.push(my_signature);
+    }
+
+    let registration_hash = transfer.registration.clone();
+    let transfer_hash = create_entry(&EntryZomes::IntegritySites(
+        EntryTypes::SiteOwnershipTransfer(transfer),
+    ))?;
+
+    create_link(
+        registration_hash,
+        transfer_hash.clone(),
+        LinkTypes::RegistrationToTransfers,
+        (),
+    )?;
+
+    Ok(transfer_hash)
+}
+
+/// Approve another owner's role grant, invite or renewal (co-owners only).
+/// Needed on sites whose owner threshold is above 1.
+#[hdk_extern]
+pub fn approve_admin_action(action_hash: ActionHash) -> ExternResult<ActionHash> {
+    let record = get(action_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Admin action not found".into())))?;
+
+    let site_id = if let Ok(Some(grant)) = record.entry().to_app_option::<SiteRoleGrant>() {
+        grant.site_id
+    } else if let Ok(Some(invite)) = record.entry().to_app_option::<SiteInvite>() {
+        invite.site_id
+    } else if let Ok(Some(renewal)) = record.entry().to_app_option::<MembershipRenewal>() {
+        renewal.site_id
+    } else {
+        return Err(wasm_error!(IdentityError::InvalidInput(
+            "Only role grants, invites and renewals can be approved".into()
+        )));
+    };
+
+    let owners = my_site_owners(&site_id)?;
+
+    let approval = SiteAdminApproval {
+        site_id,
+        registration: owners.registration,
+        ownership: owners.ownership,
+        action: action_hash.clone(),
+        approved_at: sys_time()?.as_seconds_and_nanos().0,
+    };
+
+    let approval_hash = create_entry(&EntryZomes::IntegritySites(
+        EntryTypes::SiteAdminApproval(approval),
+    ))?;
+
+    create_link(
+        action_hash,
+        approval_hash.clone(),
+        LinkTypes::AdminActionToApprovals,
+        (),
+    )?;
+
+    Ok(approval_hash)
+}
+
+/// Grant a role on a site to an agent. Only a site owner can do this; with an
+/// owner threshold above 1 the grant counts once co-owners approve it.
+#[hdk_extern]
+pub fn grant_role(input: GrantRoleInput) -> ExternResult<ActionHash> {
+    let site_id = parse_site_id(&input.site_id)?;
+    let owners = my_site_owners(&site_id)?;
+
+    let grant = SiteRoleGrant {
+        site_id: site_id.clone(),
+        registration: owners.registration,
+        ownership: owners.ownership,
+        agent: input.agent.clone(),
+        role: input.role,
+        granted_at: sys_time()?.as_seconds_and_nanos().0,
+    };
+
+    let grant_hash = create_entry(&EntryZomes::IntegritySites(EntryTypes::SiteRoleGrant(grant)))?;
+
+    create_link(
+        site_anchor(&site_id)?,
+        grant_hash.clone(),
+        LinkTypes::SiteToRoleGrants,
+        (),
+    )?;
+    create_link(
+        input.agent,
+        grant_hash.clone(),
+        LinkTypes::AgentToRoleGrants,
+        site_id.as_bytes().to_vec(),
+    )?;
+
+    Ok(grant_hash)
+}
+
+/// Revoke a role grant by deleting its creation action (grantor only).
+/// Returns the ActionHash of the Delete action.
+#[hdk_extern]
+pub fn revoke_role(grant_hash: ActionHash) -> ExternResult<ActionHash> {
+    let record = get(grant_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Role grant not found".into())))?;
+
+    record
+        .entry()
+        .to_app_option::<SiteRoleGrant>()
+        .ok()
+        .flatten()
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Entry is not a SiteRoleGrant".into())))?;
+
+    if record.action().author() != &agent_info()?.agent_initial_pubkey {
+        return Err(wasm_error!(IdentityError::NotAuthorized(
+            "Only the site owner that granted this role can revoke it".into()
+        )));
+    }
+
+    delete_entry(grant_hash)
+}
+
+/// Get the roles an agent currently holds on a site (non-revoked grants
+/// that count under the site's current owners)
+#[hdk_extern]
+pub fn get_member_roles(input: MemberRolesInput) -> ExternResult<Vec<String>> {
+    let site_id = parse_site_id(&input.site_id)?;
+
+    let Some(owners) = current_site_owners(&site_id)? else {
+        return Ok(vec![]);
+    };
+
+    let links = get_links(
+        LinkQuery::try_new(input.agent.clone(), LinkTypes::AgentToRoleGrants)?
+            .tag_prefix(LinkTag::new(site_id.as_bytes().to_vec())),
+        GetStrategy::default()
+    )?;
+
+    let mut roles: Vec<String> = Vec::new();
+    for link in links {
+        let Some(grant_hash) = link.target.into_action_hash() else {
+            continue;
+        };
+        let Some(Details::Record(record_details)) = get_details(grant_hash, GetOptions::default())? else {
+            continue;
+        };
+
+        // Revoked grants are deleted
+        if !record_details.deletes.is_empty() {
+            continue;
+        }
+
+        if let Ok(Some(grant)) = record_details.record.entry().to_app_option::<SiteRoleGrant>() {
+            if grant.site_id == site_id
+                && grant.agent == input.agent
+                && grant.registration == owners.registration
+                && !roles.contains(&grant.role)
+                && admin_action_counts(&record_details.record, &owners)?
+            {
+                roles.push(grant.role);
+            }
+        }
+    }
+
+    Ok(roles)
+}
+
+/// Create an invite to a site (site owners only). Returns the invite's ActionHash,
+/// which is handed to the invitee together with the code for code invites.
+#[hdk_extern]
+pub fn create_invite(input: CreateInviteInput) -> ExternResult<ActionHash> {
+    let site_id = parse_site_id(&input.site_id)?;
+    let owners = my_site_owners(&site_id)?;
+
+    let code_key = match &input.code {
+        Some(code) => Some(agent_key_from_ed25519(
+            invite_code_key(code)?.verifying_key().as_bytes(),
+        )?),
+        None => None,
+    };
+
+    let invite = SiteInvite {
+        site_id,
+        registration: owners.registration,
+        ownership: owners.ownership,
+        invitee: input.invitee.clone(),
+        code_key,
+        max_uses: input.max_uses,
+        expires_at: input.expires_at,
+        created_at: sys_time()?.as_seconds_and_nanos().0,
+        membership_ttl: input.membership_ttl,
+    };
+
+    let invite_hash = create_entry(&EntryZomes::IntegritySites(EntryTypes::SiteInvite(invite)))?;
+
+    // Addressed invites are discoverable by the invitee
+    if let Some(invitee) = input.invitee {
+        create_link(invitee, invite_hash.clone(), LinkTypes::AgentToInvites, ())?;
+    }
+
+    Ok(invite_hash)
+}
+
+/// Revoke an invite by deleting its creation action (site owner only).
+/// Memberships that already redeemed it are unaffected.
+#[hdk_extern]
+pub fn revoke_invite(invite_hash: ActionHash) -> ExternResult<ActionHash> {
+    let record = get(invite_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Invite not found".into())))?;
+
+    record
+        .entry()
+        .to_app_option::<SiteInvite>()
+        .ok()
+        .flatten()
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Entry is not a SiteInvite".into())))?;
+
+    if record.action().author() != &agent_info()?.agent_initial_pubkey {
+        return Err(wasm_error!(IdentityError::NotAuthorized(
+            "Only the site owner that created this invite can revoke it".into()
+        )));
+    }
+
+    delete_entry(invite_hash)
+}
+
+/// Get the non-revoked invites addressed to the current agent
+#[hdk_extern]
+pub fn get_my_invites(_: ()) -> ExternResult<Vec<Record>> {
+    let links = get_links(
+        LinkQuery::try_new(agent_info()?.agent_initial_pubkey, LinkTypes::AgentToInvites)?,
+        GetStrategy::default()
+    )?;
+
+    let mut invites = Vec::new();
+    for link in links {
+        let Some(invite_hash) = link.target.into_action_hash() else {
+            continue;
+        };
+        if let Some(Details::Record(record_details)) = get_details(invite_hash, GetOptions::default())? {
+            if record_details.deletes.is_empty() {
+                invites.push(record_details.record);
+            }
+        }
+    }
+
+    Ok(invites)
+}
+
+/// Get all sites the current agent has joined under a pairwise ID
+#[hdk_extern]
+pub fn get_my_pairwise_sites(_: ()) -> ExternResult<Vec<Record>> {
+    Ok(my_pairwise_memberships()?
+        .into_iter()
+        .map(|(record, _)| record)
+        .collect())
+}
+
+/// Get the current agent's pairwise ID for a site
+/// Deterministic: the same agent always gets the same ID for the same site
+#[hdk_extern]
+pub fn get_my_pairwise_id(site_id: String) -> ExternResult<String> {
+    let site_id = parse_site_id(&site_id)?;
+    let pairwise_key = my_pairwise_key(&site_id)?;
+    Ok(pairwise_id_from_key(
+        pairwise_key.verifying_key().as_bytes(),
+    ))
+}
+
+/// Sign data (e.g. a login challenge) with the current agent's pairwise key
+/// for a site. The site checks it against the pairwise ID alone, so it learns
+/// nothing about the agent.
+#[hdk_extern]
+pub fn sign_as_pairwise(input: PairwiseSignInput) -> ExternResult<Signature> {
+    let site_id = parse_site_id(&input.site_id)?;
+    let pairwise_key = my_pairwise_key(&site_id)?;
+    let payload = pairwise_auth_payload(&site_id, &input.data);
+    Ok(Signature(pairwise_key.sign(&payload).to_bytes()))
+}
+
+/// Verify a signature made by `sign_as_pairwise`
+#[hdk_extern]
+pub fn verify_pairwise_signature(input: PairwiseSignatureInput) -> ExternResult<bool> {
+    let site_id = parse_site_id(&input.site_id)?;
+    let pairwise_key = pairwise_key_from_id(&input.pairwise_id)?;
+    verify_signature_raw(
+        pairwise_key,
+        input.signature,
+        pairwise_auth_payload(&site_id, &input.data),
+    )
+}
+
+/// Reveal the proof binding the current agent to its pairwise ID for ONE site.
+/// Hand this only to the site concerned - it tells them nothing about other sites.
+#[hdk_extern]
+pub fn reveal_pairwise_proof(site_id: String) -> ExternResult<PairwiseProof> {
+    let site_id = parse_site_id(&site_id)?;
+    my_pairwise_proof(&site_id)
+}
+
+/// Verify a revealed pairwise proof (used by the site's own agent)
+#[hdk_extern]
+pub fn verify_pairwise_proof(proof: PairwiseProof) -> ExternResult<bool> {
+    let result = check_pairwise_proof(
+        &proof.agent,
+        &proof.site_id,
+        &proof.pairwise_id,
+        &proof.proof,
+        &proof.key_proof,
+    )?;
+
+    Ok(result == ValidateCallbackResult::Valid)
+}
+
+/// Check if current agent is a member of a site (public, blinded or pairwise)
+#[hdk_extern]
+pub fn is_site_member(input: SiteQueryInput) -> ExternResult<bool> {
+    let query = input.into_query();
+    let canonical_site_id = parse_site_id(&query.site_id)?;
+
+    if my_pairwise_memberships()?
+        .iter()
+        .any(|(_, membership)| membership.site_id == canonical_site_id)
+    {
+        return Ok(true);
+    }
+
+    let site_id = site_index_id(&canonical_site_id, query.blinding_key.as_deref())?;
+    has_membership(
+        &get_my_sites(())?,
+        &site_id,
+        &site_rules(&canonical_site_id)?,
+    )
+}
+
+/// Agents linked to this agent through non-revoked IsSamePersonEntry attestations
+fn my_linked_agents() -> ExternResult<Vec<AgentPubKey>> {
+    let response = call(
+        CallTargetCell::Local,
+        ZomeName::from("agent_linking"),
+        FunctionName::from("get_linked_agents"),
+        None,
+        agent_info()?.agent_initial_pubkey,
+    )?;
+
+    match response {
+        ZomeCallResponse::Ok(output) => output
+            .decode()
+            .map_err(|e| wasm_error!(WasmErrorInner::Serialize(e))),
+        other => Err(wasm_error!(IdentityError::CallFailed(
+            format!("Could not read linked agents: {:?}", other)
+        ))),
+    }
+}
+
+/// Check if the caller's identity is a member of a site: the caller itself,
+/// or any agent linked to it (e.g. the web agent when called from the desktop app).
+/// Returns which agent holds the membership.
+#[hdk_extern]
+pub fn is_site_member_for_identity(input: SiteQueryInput) -> ExternResult<IdentityMembership> {
+    let query = input.into_query();
+
+    if is_site_member(SiteQueryInput::Options(query.clone()))? {
+        return Ok(IdentityMembership {
+            is_member: true,
+            agent: Some(agent_info()?.agent_initial_pubkey),
+        });
+    }
+
+    for linked_agent in my_linked_agents()? {
+        let is_member = is_agent_site_member(AgentSiteQuery {
+            agent: linked_agent.clone(),
+            site_id: query.site_id.clone(),
+            blinding_key: query.blinding_key.clone(),
+        })?;
+        if is_member {
+            return Ok(IdentityMembership {
+                is_member: true,
+                agent: Some(linked_agent),
+            });
+        }
+    }
+
+    Ok(IdentityMembership {
+        is_member: false,
+        agent: None,
+    })
+}
+
+/// Record the current agent's consent for a site to access the given scopes
+#[hdk_extern]
+pub fn grant_consent(input: GrantConsentInput) -> ExternResult<ActionHash> {
+    let site_id = parse_site_id(&input.site_id)?;
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+
+    let consent = SiteConsent {
+        site_id: site_id.clone(),
+        scopes: input.scopes,
+        granted_at: sys_time()?.as_seconds_and_nanos().0,
+        expires_at: input.expires_at,
+    };
+
+    let consent_hash = create_entry(&EntryZomes::IntegritySites(EntryTypes::SiteConsent(consent)))?;
+
+    create_link(
+        my_agent_pub_key,
+        consent_hash.clone(),
+        LinkTypes::AgentToConsents,
+        site_id.as_bytes().to_vec(),
+    )?;
+
+    Ok(consent_hash)
+}
+
+/// Revoke a consent by deleting its creation action.
+/// Only the agent that granted it can revoke it (enforced in validation).
+/// Returns the ActionHash of the Delete action.
+#[hdk_extern]
+pub fn revoke_consent(consent_hash: ActionHash) -> ExternResult<ActionHash> {
+    let record = get(consent_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Consent not found".into())))?;
+
+    let entry = record
+        .entry()
+        .as_option()
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("No entry data found".into())))?;
+
+    SiteConsent::try_from(entry).map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+        "Entry is not a SiteConsent".into()
+    )))?;
+
+    if record.action().author() != &agent_info()?.agent_initial_pubkey {
+        return Err(wasm_error!(IdentityError::NotAuthorized(
+            "Only the granting agent can revoke this consent".into()
+        )));
+    }
+
+    delete_entry(consent_hash)
+}
+
+/// Get the current agent's consents for a site, including revoked and expired ones
+#[hdk_extern]
+pub fn get_consents(site_id: String) -> ExternResult<Vec<ConsentOutput>> {
+    let site_id = parse_site_id(&site_id)?;
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    let now = sys_time()?.as_seconds_and_nanos().0;
+
+    let links = get_links(
+        LinkQuery::try_new(my_agent_pub_key, LinkTypes::AgentToConsents)?
+            .tag_prefix(LinkTag::new(site_id.as_bytes().to_vec())),
+        GetStrategy::default()
+    )?;
+
+    let mut consents = Vec::new();
+    for link in links {
+        let consent_hash = match link.target.into_action_hash() {
+            Some(hash) => hash,
+            None => continue,
+        };
+
+        let record_details = match get_details(consent_hash.clone(), GetOptions::default())? {
+            Some(Details::Record(record_details)) => record_details,
+            _ => continue,
+        };
+
+        let consent = match record_details.record.entry().as_option() {
+            Some(entry) => match SiteConsent::try_from(entry) {
+                Ok(consent) if consent.site_id == site_id => consent,
+                _ => continue,
+            },
+            None => continue,
+        };
+
+        let revoked_at = record_details
+            .deletes
+            .iter()
+            .map(|delete| delete.action().timestamp())
+            .min();
+        let expired = consent.expires_at.is_some_and(|expires_at| expires_at <= now);
+
+        consents.push(ConsentOutput {
+            consent_hash,
+            active: revoked_at.is_none() && !expired,
+            consent,
+            revoked_at,
+        });
+    }
+
+    Ok(consents)
+}
+
+/// Renew an expiring membership of a site (site owners only), e.g. when a
+/// subscription is paid. Returns the renewal's ActionHash.
+#[hdk_extern]
+pub fn renew_membership(input: RenewMembershipInput) -> ExternResult<ActionHash> {
+    let membership_record = get(input.membership_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Membership not found".into())))?;
+    let membership: SiteMembership = membership_record
+        .entry()
+        .to_app_option()
+        .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+            "Could not deserialize membership".into()
+        )))?
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Entry is not a SiteMembership".into())))?;
+
+    if membership.expires_at.is_none() {
+        return Err(wasm_error!(IdentityError::InvalidInput(
+            "Membership does not expire, so it cannot be renewed".into()
+        )));
+    }
+    // Blinded memberships can't be matched to a registration
+    let owners = my_site_owners(&membership.site_id)?;
+
+    let renewal = MembershipRenewal {
+        membership: input.membership_hash.clone(),
+        site_id: membership.site_id,
+        registration: owners.registration,
+        ownership: owners.ownership,
+        expires_at: input.expires_at,
+        renewed_at: sys_time()?.as_seconds_and_nanos().0,
+    };
+
+    let renewal_hash = create_entry(&EntryZomes::IntegritySites(EntryTypes::MembershipRenewal(renewal)))?;
+
+    create_link(
+        input.membership_hash,
+        renewal_hash.clone(),
+        LinkTypes::MembershipToRenewals,
+        (),
+    )?;
+
+    Ok(renewal_hash)
+}
+
+/// Get the current validity window of a membership (its own expiry plus renewals)
+#[hdk_extern]
+pub fn get_membership_validity(membership_hash: ActionHash) -> ExternResult<MembershipValidity> {
+    let membership_record = get(membership_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Membership not found".into())))?;
+    let membership: SiteMembership = membership_record
+        .entry()
+        .to_app_option()
+        .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+            "Could not deserialize membership".into()
+        )))?
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Entry is not a SiteMembership".into())))?;
+
+    membership_validity(&membership_hash, &membership, &site_rules(&membership.site_id)?)
+}
+
+/// Get the latest metadata record for a membership (follows the update chain)
+#[hdk_extern]
+pub fn get_membership_metadata(membership_hash: ActionHash) -> ExternResult<Option<Record>> {
+    let links = get_links(
+        LinkQuery::try_new(membership_hash, LinkTypes::MembershipToMetadata)?,
+        GetStrategy::default()
+    )?;
+
+    // Only one metadata entry per membership; updates hang off it
+    if let Some(link) = links.first() {
+        let mut current_hash = ActionHash::try_from(link.target.clone())
+            .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+                "Invalid metadata hash".into()
+            )))?;
+
+        loop {
+            let details = get_details(current_hash.clone(), GetOptions::default())?
+                .ok_or(wasm_error!(IdentityError::NotFound("Metadata not found in chain".into())))?;
+
+            match details {
+                Details::Record(record_details) => {
+                    if let Some(latest_update) = record_details.updates.last() {
+                        current_hash = latest_update.action_address().clone();
+                    } else {
+                        return Ok(Some(record_details.record));
+                    }
+                }
+                _ => return Err(wasm_error!(IdentityError::Internal(
+                    "Expected Record details".into()
+                ))),
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Create or update the metadata on one of the caller's memberships.
+/// Only the member can do this; size is capped by validation.
+#[hdk_extern]
+pub fn set_membership_metadata(input: SetMetadataInput) -> ExternResult<Record> {
+    if input.data.len() > MAX_MEMBERSHIP_METADATA_BYTES {
+        return Err(wasm_error!(IdentityError::InvalidInput(format!(
+            "Membership metadata must be at most {} bytes",
+            MAX_MEMBERSHIP_METADATA_BYTES
+        ))));
+    }
+
+    let membership_record = get(input.membership_hash.clone(), GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::NotFound("Membership not found".into())))?;
+    if membership_record.action().author() != &agent_info()?.agent_initial_pubkey {
+        return Err(wasm_error!(IdentityError::NotAuthorized(
+            "Only the member can set membership metadata".into()
+        )));
+    }
+
+    let metadata = MembershipMetadata {
+        membership: input.membership_hash.clone(),
+        version: input.version,
+        data: input.data,
+        updated_at: sys_time()?.as_seconds_and_nanos().0,
+    };
+
+    let metadata_hash = match get_membership_metadata(input.membership_hash.clone())? {
+        Some(current) => update_entry(
+            current.action_address().clone(),
+            &EntryZomes::IntegritySites(EntryTypes::MembershipMetadata(metadata)),
+        )?,
+        None => {
+            let hash = create_entry(&EntryZomes::IntegritySites(EntryTypes::MembershipMetadata(metadata)))?;
+            create_link(
+                input.membership_hash,
+                hash.clone(),
+                LinkTypes::MembershipToMetadata,
+                (),
+            )?;
+            hash
+        }
+    };
+
+    let record = get(metadata_hash, GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::Internal(
+            "Could not find the membership metadata".into()
+        )))?;
+
+    Ok(record)
+}
+
+/// Read an agent's DID from the users zome in this cell
+fn member_did(member: AgentPubKey) -> ExternResult<String> {
+    let response = call(
+        CallTargetCell::Local,
+        ZomeName::from("users"),
+        FunctionName::from("get_latest_profile"),
+        None,
+        member,
+    )?;
+
+    let profile: Option<ProfileDid> = match response {
+        ZomeCallResponse::Ok(output) => output
+            .decode()
+            .map_err(|e| wasm_error!(WasmErrorInner::Serialize(e)))?,
+        other => return Err(wasm_error!(IdentityError::CallFailed(
+            format!("Could not read the member's profile: {:?}", other)
+        ))),
+    };
+
+    let profile = profile
+        .ok_or(wasm_error!(IdentityError::ProfileNotFound("Member has no profile".into())))?;
+
+    Ok(profile.did)
+}
+
+/// Why a credential is not acceptable right now, or None if it is
+fn credential_problem(credential: &MembershipCredential) -> ExternResult<Option<String>> {
+    let payload = &credential.payload;
+
+    if canonicalize_site_id(&payload.site_id).ok().as_deref() != Some(payload.site_id.as_str()) {
+        return Ok(Some("site_id is not canonical".to_string()));
+    }
+
+    if !verify_signature(
+        credential.issuer.clone(),
+        credential.signature.clone(),
+        membership_credential_payload_bytes(payload),
+    )? {
+        return Ok(Some("Signature does not verify against the issuer".to_string()));
+    }
+
+    if sys_time()?.as_seconds_and_nanos().0 >= payload.expires_at {
+        return Ok(Some("Credential has expired".to_string()));
+    }
+
+    let is_current_owner = match current_site_owners(&payload.site_id)? {
+        Some(owners) => {
+            owners.registration == credential.registration
+                && owners.owners.contains(&credential.issuer)
+        }
+        None => false,
+    };
+    if !is_current_owner {
+        return Ok(Some("Issuer is not a current site owner".to_string()));
+    }
+
+    Ok(None)
+}
+
+/// Issue a portable membership credential for a member (site owners only),
+/// signed with the caller's key. Returns the msgpack-encoded
+/// MembershipCredential, which the member can present anywhere; checking it
+/// only needs the issuing owner's key.
+#[hdk_extern]
+pub fn issue_membership_credential(input: IssueCredentialInput) -> ExternResult<Vec<u8>> {
+    let site_id = parse_site_id(&input.site_id)?;
+    let owners = my_site_owners(&site_id)?;
+    let issuer = agent_info()?.agent_initial_pubkey;
+
+    let roles = get_member_roles(MemberRolesInput {
+        site_id: site_id.clone(),
+        agent: input.member.clone(),
+    })?;
+    let is_member = is_agent_site_member(AgentSiteQuery {
+        agent: input.member.clone(),
+        site_id: site_id.clone(),
+        blinding_key: None,
+    })?;
+    if !is_member && roles.is_empty() {
+        return Err(wasm_error!(IdentityError::NotAMember(
+            format!("Agent is not a member of '{}'", site_id)
+        )));
+    }
+
+    // Highest granted role, in SITE_ROLES order
+    let role = SITE_ROLES
+        .iter()
+        .find(|role| roles.iter().any(|granted| granted == *role))
+        .unwrap_or(&"member")
+        .to_string();
+
+    let issued_at = sys_time()?.as_seconds_and_nanos().0;
+    let expires_at = input
+        .expires_at
+        .unwrap_or(issued_at + DEFAULT_CREDENTIAL_TTL_SECONDS);
+    if expires_at <= issued_at {
+        return Err(wasm_error!(IdentityError::InvalidInput(
+            "expires_at must be in the future".into()
+        )));
+    }
+
+    let payload = MembershipCredentialPayload {
+        member_did: member_did(input.member.clone())?,
+        member: input.member,
+        site_id,
+        role,
+        issued_at,
+        expires_at,
+    };
+
+    let signature = sign(issuer.clone(), membership_credential_payload_bytes(&payload))?;
+
+    let credential = MembershipCredential {
+        payload,
+        issuer,
+        registration: owners.registration,
+        signature,
+    };
+
+    let bytes = SerializedBytes::try_from(credential)
+        .map_err(|e| wasm_error!(WasmErrorInner::Serialize(e)))?;
+
+    Ok(bytes.bytes().to_vec())
+}
+
+/// Verify a membership credential: signature, expiry, and that the
+/// issuer is one of the site's current owners
+#[hdk_extern]
+pub fn verify_membership_credential(bytes: Vec<u8>) -> ExternResult<CredentialVerification> {
+    let credential =
+        match MembershipCredential::try_from(SerializedBytes::from(UnsafeBytes::from(bytes))) {
+            Ok(credential) => credential,
+            Err(_) => {
+                return Ok(CredentialVerification {
+                    valid: false,
+                    reason: Some("Bytes are not a MembershipCredential".to_string()),
+                    credential: None,
+                })
+            }
+        };
+
+    let reason = credential_problem(&credential)?;
+
+    Ok(CredentialVerification {
+        valid: reason.is_none(),
+        reason,
+        credential: Some(credential),
+    })
+}
+
+// ── Signals ─────────────────────────────────────────────────────────
+
+/// Emit a signal for each committed membership create or delete
+#[hdk_extern(infallible)]
+pub fn post_commit(committed_actions: Vec<SignedActionHashed>) {
+    for action in committed_actions {
+        if let Err(err) = signal_action(action) {
+            error!("Error signaling new action: {:?}", err);
+        }
+    }
+}
+
+fn signal_action(action: SignedActionHashed) -> ExternResult<()> {
+    let (membership_hash, entry_type, joined) = match action.action() {
+        Action::Create(create) => (action.as_hash().clone(), create.entry_type.clone(), true),
+        // A delete doesn't name the entry type; the deleted action (on this chain) does
+        Action::Delete(delete) => {
+            let deleted = must_get_action(delete.deletes_address.clone())?;
+            let Some(entry_type) = deleted.action().entry_type().cloned() else {
+                return Ok(());
+            };
+            (delete.deletes_address.clone(), entry_type, false)
+        }
+        _ => return Ok(()),
+    };
+    let Some((site_id, pairwise)) = committed_membership(&membership_hash, &entry_type)? else {
+        return Ok(());
+    };
+
+    let signal = if joined {
+        SitesSignal::SiteJoined { site_id, membership_hash, pairwise }
+    } else {
+        SitesSignal::SiteLeft { site_id, membership_hash, pairwise }
+    };
+    emit_signal(signal)
+}
+
+/// Site ID and pairwise flag of the membership an action created, if it created one.
+/// Only membership entry types are looked up.
+fn committed_membership(
+    action_hash: &ActionHash,
+    entry_type: &EntryType,
+) -> ExternResult<Option<(String, bool)>> {
+    let site_membership: EntryType = UnitEntryTypes::SiteMembership.try_into()?;
+    if *entry_type == site_membership {
+        let Some(record) = get(action_hash.clone(), GetOptions::default())? else {
+            return Ok(None);
+        };
+        return Ok(record
+            .entry()
+            .to_app_option::<SiteMembership>()
+            .ok()
+            .flatten()
+            .map(|membership| (membership.site_id, false)));
+    }
+
+    let pairwise_membership: EntryType = UnitEntryTypes::PairwiseSiteMembership.try_into()?;
+    if *entry_type == pairwise_membership {
+        return Ok(chain_pairwise_memberships()?
+            .into_iter()
+            .find(|(record, _)| record.action_address() == action_hash)
+            .map(|(_, membership)| (membership.site_id, true)));
+    }
+
+    Ok(None)
+}
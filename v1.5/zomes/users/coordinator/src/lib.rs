@@ -0,0 +1,1467 @@
+use hdk::prelude::*;
+use sha2::{Digest, Sha256};
+use users_integrity::*;
+use users_profile::{LatestUserProfile, UserProfileV1, UsersSignal, LATEST_PROFILE_SCHEMA};
+use identity_errors::IdentityError;
+
+#[hdk_dependent_entry_types]
+enum EntryZomes {
+    IntegrityUsers(users_integrity::EntryTypes),
+}
+
+/// Register a new user profile
+/// This creates the profile entry and links it to the agent
+/// v1.2: Requires DID (no email_hash or display_name for security)
+/// Stored in the latest profile schema
+#[hdk_extern]
+pub fn register_user(profile: LatestUserProfile) -> ExternResult<Record> {
+    // Validate required fields
+    if profile.did.is_empty() {
+        return Err(wasm_error!(IdentityError::InvalidInput(
+            "DID is required for identity v1.2".into()
+        )));
+    }
+    let profile = stored_profile(&profile)?;
+    
+    // Create the profile entry wrapped in the dependent types
+    let profile_hash = create_entry(&EntryZomes::IntegrityUsers(EntryTypes::UserProfile(profile)))?;
+    
+    // Create a link from the agent's public key to their profile
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    create_link(
+        my_agent_pub_key.clone(),
+        profile_hash.clone(),
+        LinkTypes::AgentToProfile,
+        (),
+    )?;
+    
+    // Return the created record
+    let record = get(profile_hash, GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::Internal(
+            "Could not find the newly created profile".into()
+        )))?;
+    
+    Ok(record)
+}
+
+/// Get the current agent's profile (follows update chain recursively)
+/// ⚠️ CRITICAL: Uses LOOP to follow ENTIRE update chain (not just .last())
+/// Returns the entry as stored, `{ schema, payload }`; get_latest_profile decodes it
+#[hdk_extern]
+pub fn get_my_profile(_: ()) -> ExternResult<Option<Record>> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    
+    // Get links from agent to profile
+    let links = get_links(
+        LinkQuery::try_new(my_agent_pub_key, LinkTypes::AgentToProfile)?,
+        GetStrategy::default()
+    )?;
+    
+    // Get the first (should only be one) profile
+    if let Some(link) = links.first() {
+        let mut current_hash = ActionHash::try_from(link.target.clone())
+            .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+                "Invalid profile hash".into()
+            )))?;
+        
+        // ⚠️ CRITICAL: Use LOOP to recursively follow ENTIRE update chain
+        loop {
+            let details = get_details(current_hash.clone(), GetOptions::default())?
+                .ok_or(wasm_error!(IdentityError::NotFound("Profile not found in chain".into())))?;
+            
+            match details {
+                Details::Record(record_details) => {
+                    // Check if there's an update
+                    if let Some(latest_update) = record_details.updates.last() {
+                        // Continue following the chain
+                        current_hash = latest_update.action_address().clone();
+                    } else {
+                        // No more updates - this is the latest record
+                        return Ok(Some(record_details.record));
+                    }
+                }
+                _ => return Err(wasm_error!(IdentityError::Internal(
+                    "Expected Record details".into()
+                ))),
+            }
+        }
+    }
+    
+    Ok(None)
+}
+
+/// Update the current agent's profile (stored in the latest schema)
+#[hdk_extern]
+pub fn update_profile(profile: LatestUserProfile) -> ExternResult<Record> {
+    // Get the current profile
+    let current_profile_record = get_my_profile(())?
+        .ok_or(wasm_error!(IdentityError::ProfileNotFound("No profile found to update".into())))?;
+    let profile = stored_profile(&profile)?;
+    
+    // Update the entry
+    let updated_profile_hash = update_entry(
+        current_profile_record.action_address().clone(),
+        &EntryZomes::IntegrityUsers(EntryTypes::UserProfile(profile)),
+    )?;
+    
+    // Return the updated record
+    let record = get(updated_profile_hash, GetOptions::default())?
+        .ok_or(wasm_error!(IdentityError::Internal("Could not find the updated profile".into())))?;
+    
+    Ok(record)
+}
+
+/// Get any user's profile by their agent public key (follows update chain recursively)
+/// ⚠️ CRITICAL: Uses LOOP to follow ENTIRE update chain (not just .last())
+/// Returns the entry as stored, `{ schema, payload }`; get_latest_profile decodes it
+#[hdk_extern]
+pub fn get_profile(agent: AgentPubKey) -> ExternResult<Option<Record>> {
+    // Get links from the specified agent to their profile
+    let links = get_links(
+        LinkQuery::try_new(agent, LinkTypes::AgentToProfile)?,
+        GetStrategy::default()
+    )?;
+    
+    // Get the first (should only be one) profile
+    if let Some(link) = links.first() {
+        let mut current_hash = ActionHash::try_from(link.target.clone())
+            .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+                "Invalid profile hash".into()
+            )))?;
+        
+        // ⚠️ CRITICAL: Use LOOP to recursively follow ENTIRE update chain
+        loop {
+            let details = get_details(current_hash.clone(), GetOptions::default())?
+                .ok_or(wasm_error!(IdentityError::NotFound("Profile not found in chain".into())))?;
+            
+            match details {
+                Details::Record(record_details) => {
+                    // Check if there's an update
+                    if let Some(latest_update) = record_details.updates.last() {
+                        // Continue following the chain
+                        current_hash = latest_update.action_address().clone();
+                    } else {
+                        // No more updates - this is the latest record
+                        return Ok(Some(record_details.record));
+                    }
+                }
+                _ => return Err(wasm_error!(IdentityError::Internal(
+                    "Expected Record details".into()
+                ))),
+            }
+        }
+    }
+    
+    Ok(None)
+}
+
+/// A profile as stored by an earlier DNA version, PII included.
+/// Only ever read from that version's cell: the PII fields are dropped on import.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum LegacyUserProfile {
+    /// v1.2 and v1.3: DID plus profile picture
+    V1_2 {
+        did: String,
+        profile_picture: String,
+        has_custom_picture: bool,
+        created_at: i64,
+        updated_at: i64,
+    },
+    /// v1.4: DID only (tried after V1_2, which it would otherwise also match)
+    V1_4 {
+        did: String,
+        created_at: i64,
+        updated_at: i64,
+    },
+    V1_1 {
+        email_hash: String,
+        display_name: String,
+        profile_picture: String,
+        has_custom_picture: bool,
+        created_at: i64,
+        updated_at: i64,
+    },
+    V1_0 {
+        email_hash: String,
+        display_name: String,
+        created_at: i64,
+    },
+}
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Base58 (Bitcoin alphabet) encoding
+fn base58btc(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+
+    // Little-endian base58 digits
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in bytes {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let mut encoded = "1".repeat(zeros);
+    encoded.extend(digits.iter().rev().map(|digit| BASE58_ALPHABET[*digit as usize] as char));
+    encoded
+}
+
+/// `did:key` for an agent's Ed25519 key: multibase base58btc ('z') of the
+/// ed25519-pub multicodec prefix (0xed 0x01) followed by the raw 32-byte key
+fn did_key(agent: &AgentPubKey) -> String {
+    let mut bytes = vec![0xed, 0x01];
+    bytes.extend_from_slice(agent.get_raw_32());
+    format!("did:key:z{}", base58btc(&bytes))
+}
+
+/// Import this agent's profile from a v1.0-v1.4 DNA installed in the same
+/// hApp under `role_name`, dropping every PII field (email_hash,
+/// display_name, profile_picture, has_custom_picture). Validation rejects
+/// any profile that still carries them.
+///
+/// The profile is read from the agent's own cell over a cross-cell call,
+/// never taken from the caller, so the DID is the one this agent key
+/// published on the previous DNA (or a did:key for the agent, for versions
+/// before DIDs).
+#[hdk_extern]
+pub fn import_legacy_profile(role_name: String) -> ExternResult<Record> {
+    if get_my_profile(())?.is_some() {
+        return Err(wasm_error!(IdentityError::ProfileExists(
+            "This agent already has a profile".into()
+        )));
+    }
+
+    register_user(profile_from_legacy_role(&role_name)?)
+}
+
+/// The PII-free profile a legacy profile imports as
+fn legacy_profile(profile: LegacyUserProfile) -> ExternResult<LatestUserProfile> {
+    let (legacy_did, created_at, updated_at) = match profile {
+        LegacyUserProfile::V1_2 { did, created_at, updated_at, .. }
+        | LegacyUserProfile::V1_4 { did, created_at, updated_at } => (Some(did), created_at, updated_at),
+        LegacyUserProfile::V1_1 { created_at, updated_at, .. } => (None, created_at, updated_at),
+        LegacyUserProfile::V1_0 { created_at, .. } => (None, created_at, created_at),
+    };
+
+    let did = match legacy_did {
+        Some(did) => did,
+        None => did_key(&agent_info()?.agent_initial_pubkey),
+    };
+
+    Ok(LatestUserProfile {
+        did,
+        created_at,
+        updated_at,
+    })
+}
+
+/// A profile in the latest schema, as the entry stores it
+fn stored_profile(profile: &LatestUserProfile) -> ExternResult<UserProfile> {
+    let payload = SerializedBytes::try_from(profile.clone())
+        .map_err(|e| wasm_error!(WasmErrorInner::Serialize(e)))?;
+    Ok(UserProfile {
+        schema: LATEST_PROFILE_SCHEMA,
+        payload: payload.bytes().to_vec(),
+    })
+}
+
+/// Decode a stored profile into the latest schema, whichever version it was
+/// written in. Each new schema version adds an arm here that brings the
+/// stored shape forward (see the users_profile crate).
+fn read_profile(profile: &UserProfile) -> ExternResult<LatestUserProfile> {
+    let payload = SerializedBytes::from(UnsafeBytes::from(profile.payload.clone()));
+    match profile.schema {
+        1 => UserProfileV1::try_from(payload).map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+            "Could not deserialize profile".into()
+        ))),
+        schema => Err(wasm_error!(IdentityError::WrongEntryType(format!(
+            "Unknown profile schema {}",
+            schema
+        )))),
+    }
+}
+
+/// Read a profile record in the latest schema, whichever version is stored
+fn latest_profile(record: Option<Record>) -> ExternResult<Option<LatestUserProfile>> {
+    let Some(record) = record else {
+        return Ok(None);
+    };
+
+    let profile: UserProfile = record
+        .entry()
+        .to_app_option()
+        .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+            "Could not deserialize profile".into()
+        )))?
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Profile entry is None".into())))?;
+
+    Ok(Some(read_profile(&profile)?))
+}
+
+/// Get the current agent's profile, normalized to the latest schema
+#[hdk_extern]
+pub fn get_my_latest_profile(_: ()) -> ExternResult<Option<LatestUserProfile>> {
+    latest_profile(get_my_profile(())?)
+}
+
+/// Get any user's profile, normalized to the latest schema
+#[hdk_extern]
+pub fn get_latest_profile(agent: AgentPubKey) -> ExternResult<Option<LatestUserProfile>> {
+    latest_profile(get_profile(agent)?)
+}
+
+/// Export all data from this DNA (for migration TO next version)
+/// Legacy: latest profile only, in the v1.4 shape - use export_identity for the full signed bundle
+#[hdk_extern]
+pub fn export_all_data(_: ()) -> ExternResult<LatestUserProfile> {
+    let profile_record = get_my_profile(())?
+        .ok_or(wasm_error!(IdentityError::ProfileNotFound("No profile found to export".into())))?;
+    
+    let profile: UserProfile = profile_record
+        .entry()
+        .to_app_option()
+        .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+            "Could not deserialize profile".into()
+        )))?
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Profile entry is None".into())))?;
+    
+    read_profile(&profile)
+}
+
+/// Fields of a sites_integrity::SiteMembership entry the export carries
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+struct MembershipData {
+    site_id: String,
+    joined_at: i64,
+    #[serde(default)]
+    invite: Option<ActionHash>,
+    #[serde(default)]
+    expires_at: Option<i64>,
+}
+
+/// The part of the sites zome's MembershipValidity the export needs
+#[derive(Serialize, Deserialize, Debug)]
+struct MembershipActive {
+    active: bool,
+}
+
+/// Fields of an agent_linking_integrity::IsSamePersonEntry
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+struct AgentLinkData {
+    agent_a: AgentPubKey,
+    signature_a: Signature,
+    agent_b: AgentPubKey,
+    signature_b: Signature,
+    created_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delegation: Option<LinkDelegationData>,
+}
+
+/// Fields of an agent_linking_integrity::LinkDelegation
+#[derive(Serialize, Deserialize, Debug)]
+struct LinkDelegationData {
+    signer: AgentPubKey,
+    signature: Signature,
+}
+
+/// agent_linking's LinkDelegationInput
+#[derive(Serialize, Debug)]
+struct LinkDelegationRequest {
+    agent_a: AgentPubKey,
+    agent_b: AgentPubKey,
+    delegate: AgentPubKey,
+}
+
+/// agent_linking's ImportLinksInput
+#[derive(Serialize, Debug)]
+struct ImportLinksRequest {
+    signer: AgentPubKey,
+    links: Vec<AgentLinkData>,
+}
+
+/// The skipped part of sites' MembershipsImport
+#[derive(Deserialize, Debug)]
+struct MembershipsImportData {
+    skipped: Vec<SkippedMembership>,
+}
+
+/// The skipped part of agent_linking's LinksImport
+#[derive(Deserialize, Debug)]
+struct LinksImportData {
+    skipped: Vec<SkippedLink>,
+}
+
+/// What an import brought over. Memberships and links that can't be
+/// re-created on this DNA are listed here instead of failing the import.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportReport {
+    /// The imported profile
+    pub profile: Record,
+    pub skipped_memberships: Vec<SkippedMembership>,
+    pub skipped_links: Vec<SkippedLink>,
+}
+
+/// A site membership that wasn't imported (sites' SkippedMembership)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkippedMembership {
+    pub site_id: String,
+    pub reason: String,
+}
+
+/// An agent link that wasn't imported (agent_linking's SkippedLink)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkippedLink {
+    pub agent_a: AgentPubKey,
+    pub agent_b: AgentPubKey,
+    pub reason: String,
+}
+
+/// Call another coordinator zome in this cell
+fn call_zome<I, O>(zome_name: &str, fn_name: &str, payload: I) -> ExternResult<O>
+where
+    I: Serialize + std::fmt::Debug,
+    O: serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let response = call(
+        CallTargetCell::Local,
+        ZomeName::from(zome_name),
+        FunctionName::from(fn_name),
+        None,
+        payload,
+    )?;
+
+    match response {
+        ZomeCallResponse::Ok(output) => output
+            .decode()
+            .map_err(|e| wasm_error!(WasmErrorInner::Serialize(e))),
+        other => Err(wasm_error!(IdentityError::CallFailed(format!(
+            "Call to {}.{} failed: {:?}",
+            zome_name, fn_name, other
+        )))),
+    }
+}
+
+/// All of an agent's profile records, from the original create through
+/// every update (oldest first)
+fn profile_chain(agent: AgentPubKey) -> ExternResult<Vec<Record>> {
+    let links = get_links(
+        LinkQuery::try_new(agent, LinkTypes::AgentToProfile)?,
+        GetStrategy::default()
+    )?;
+
+    let Some(link) = links.first() else {
+        return Ok(vec![]);
+    };
+    let mut current_hash = ActionHash::try_from(link.target.clone())
+        .map_err(|_| wasm_error!(IdentityError::WrongEntryType("Invalid profile hash".into())))?;
+
+    let mut chain = Vec::new();
+    loop {
+        let details = get_details(current_hash.clone(), GetOptions::default())?
+            .ok_or(wasm_error!(IdentityError::NotFound("Profile not found in chain".into())))?;
+
+        match details {
+            Details::Record(record_details) => {
+                let next_hash = record_details
+                    .updates
+                    .last()
+                    .map(|update| update.action_address().clone());
+                chain.push(record_details.record);
+
+                match next_hash {
+                    Some(hash) => current_hash = hash,
+                    None => return Ok(chain),
+                }
+            }
+            _ => return Err(wasm_error!(IdentityError::Internal("Expected Record details".into()))),
+        }
+    }
+}
+
+/// Everything this identity holds in this DNA: profile history, active site
+/// memberships and non-revoked agent links
+fn collect_identity_export(recipient: Option<AgentPubKey>) -> ExternResult<IdentityExport> {
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+
+    let mut profile_history = Vec::new();
+    for record in profile_chain(my_agent_pub_key.clone())? {
+        let profile: UserProfile = record
+            .entry()
+            .to_app_option()
+            .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+                "Could not deserialize profile".into()
+            )))?
+            .ok_or(wasm_error!(IdentityError::WrongEntryType("Profile entry is None".into())))?;
+        profile_history.push(ExportedProfile {
+            action_hash: record.action_address().clone(),
+            profile,
+        });
+    }
+    if profile_history.is_empty() {
+        return Err(wasm_error!(IdentityError::ProfileNotFound(
+            "No profile found to export".into()
+        )));
+    }
+
+    let mut memberships = Vec::new();
+    let membership_records: Vec<Record> = call_zome("sites", "get_my_sites", ())?;
+    for record in membership_records {
+        let Ok(Some(membership)) = record.entry().to_app_option::<MembershipData>() else {
+            continue;
+        };
+        // Expired memberships are left behind
+        let validity: MembershipActive =
+            call_zome("sites", "get_membership_validity", record.action_address().clone())?;
+        if !validity.active {
+            continue;
+        }
+        memberships.push(ExportedMembership {
+            action_hash: record.action_address().clone(),
+            site_id: membership.site_id,
+            joined_at: membership.joined_at,
+            invite: membership.invite,
+            expires_at: membership.expires_at,
+        });
+    }
+
+    // A recipient key can only publish the links it is delegated
+    let delegate = recipient.clone().filter(|recipient| recipient != &my_agent_pub_key);
+    let mut agent_links = Vec::new();
+    let link_records: Vec<Record> =
+        call_zome("agent_linking", "get_link_records", my_agent_pub_key.clone())?;
+    for record in link_records {
+        if let Ok(Some(link)) = record.entry().to_app_option::<AgentLinkData>() {
+            let delegation = match &delegate {
+                Some(delegate) => {
+                    let delegation: LinkDelegationData = call_zome(
+                        "agent_linking",
+                        "sign_link_delegation",
+                        LinkDelegationRequest {
+                            agent_a: link.agent_a.clone(),
+                            agent_b: link.agent_b.clone(),
+                            delegate: delegate.clone(),
+                        },
+                    )?;
+                    Some(delegation.signature)
+                }
+                None => None,
+            };
+            agent_links.push(ExportedAgentLink {
+                action_hash: record.action_address().clone(),
+                agent_a: link.agent_a,
+                signature_a: link.signature_a,
+                agent_b: link.agent_b,
+                signature_b: link.signature_b,
+                created_at: link.created_at,
+                delegation,
+            });
+        }
+    }
+
+    Ok(IdentityExport {
+        version: IDENTITY_EXPORT_VERSION,
+        agent: my_agent_pub_key,
+        recipient,
+        dna_hash: dna_info()?.hash,
+        exported_at: sys_time()?.as_seconds_and_nanos().0,
+        profile_history,
+        memberships,
+        agent_links,
+        lineage: my_migrations()?
+            .into_iter()
+            .map(|(_, provenance)| provenance.export)
+            .collect(),
+    })
+}
+
+/// Export everything this identity holds in this DNA (for migration TO next version),
+/// signed by the current agent. Name a `recipient` when the importing agent
+/// on the next version has a different key.
+#[hdk_extern]
+pub fn export_identity(recipient: Option<AgentPubKey>) -> ExternResult<SignedIdentityExport> {
+    let export = collect_identity_export(recipient)?;
+    let export_bytes = SignedIdentityExport::encode_export(&export)?;
+    let signature = sign(export.agent, identity_export_signing_bytes(&export_bytes))?;
+
+    Ok(SignedIdentityExport {
+        export_bytes,
+        signature,
+    })
+}
+
+/// This agent's MigrationProvenance records (read from the source chain)
+fn my_migrations() -> ExternResult<Vec<(Record, MigrationProvenance)>> {
+    let filter = ChainQueryFilter::new()
+        .entry_type(UnitEntryTypes::MigrationProvenance.try_into()?)
+        .include_entries(true);
+
+    let mut migrations = Vec::new();
+    for record in query(filter)? {
+        if let Ok(Some(provenance)) = record.entry().to_app_option::<MigrationProvenance>() {
+            migrations.push((record, provenance));
+        }
+    }
+
+    Ok(migrations)
+}
+
+/// Import a signed identity export from a previous DNA version.
+/// The export must verify against the exporting agent and be meant for the
+/// caller (as exporting agent or named recipient); validation checks the same.
+/// Records a MigrationProvenance linking the old DNA and agent to the new profile.
+/// Importing again from the same DNA and agent returns the already imported
+/// profile, with nothing reported skipped.
+#[hdk_extern]
+pub fn import_data(signed: SignedIdentityExport) -> ExternResult<ImportReport> {
+    import_signed_export(signed)
+}
+
+/// Verify a signed export and import it: the profile, its site memberships
+/// and its agent links. The sites and agent_linking calls run in this cell
+/// and share this call's workspace, so every write is committed together or
+/// not at all. A bad export or a conflicting profile fails the import;
+/// memberships and links that can't be carried over are skipped and reported.
+fn import_signed_export(signed: SignedIdentityExport) -> ExternResult<ImportReport> {
+    let export = check_signed_export(&signed)?
+        .map_err(|reason| wasm_error!(IdentityError::ExportRejected(reason)))?;
+
+    let my_agent_pub_key = agent_info()?.agent_initial_pubkey;
+    if export.agent != my_agent_pub_key && export.recipient.as_ref() != Some(&my_agent_pub_key) {
+        return Err(wasm_error!(IdentityError::ExportRejected(
+            "Export is not meant for this agent".into()
+        )));
+    }
+    if export.dna_hash == dna_info()?.hash {
+        return Err(wasm_error!(IdentityError::ExportRejected(
+            "Export comes from this DNA; nothing to migrate".into()
+        )));
+    }
+
+    // Already imported from this DNA and agent - hand back the profile
+    if let Some((_, provenance)) = my_migrations()?.into_iter().find(|(_, provenance)| {
+        provenance.export.export().is_ok_and(|imported| {
+            imported.dna_hash == export.dna_hash && imported.agent == export.agent
+        })
+    }) {
+        let profile = get(provenance.profile, GetOptions::default())?
+            .ok_or(wasm_error!(IdentityError::Internal(
+                "Could not find the imported profile".into()
+            )))?;
+        return Ok(ImportReport {
+            profile,
+            skipped_memberships: Vec::new(),
+            skipped_links: Vec::new(),
+        });
+    }
+
+    let profile = export
+        .profile_history
+        .last()
+        .map(|exported| exported.profile.clone())
+        .ok_or(wasm_error!(IdentityError::ExportRejected("Export has no profile".into())))?;
+
+    // A profile left by an interrupted import is reused if it matches
+    let profile_record = match get_my_profile(())? {
+        Some(existing) => {
+            let existing_profile: UserProfile = existing
+                .entry()
+                .to_app_option()
+                .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+                    "Could not deserialize profile".into()
+                )))?
+                .ok_or(wasm_error!(IdentityError::WrongEntryType("Profile entry is None".into())))?;
+            if existing_profile != profile {
+                return Err(wasm_error!(IdentityError::ProfileExists(
+                    "This agent already has a different profile".into()
+                )));
+            }
+            existing
+        }
+        None => {
+            let hash = create_entry(&EntryZomes::IntegrityUsers(EntryTypes::UserProfile(profile)))?;
+            create_link(
+                my_agent_pub_key.clone(),
+                hash.clone(),
+                LinkTypes::AgentToProfile,
+                (),
+            )?;
+
+            get(hash, GetOptions::default())?
+                .ok_or(wasm_error!(IdentityError::Internal(
+                    "Could not find the newly created entry".into()
+                )))?
+        }
+    };
+
+    let site_ids: Vec<String> = export
+        .memberships
+        .iter()
+        .map(|membership| membership.site_id.clone())
+        .collect();
+    let memberships: MembershipsImportData = call_zome("sites", "import_memberships", site_ids)?;
+
+    // Links are authorized by the export's signer, which may be an older key than ours
+    let links: Vec<AgentLinkData> = export
+        .agent_links
+        .iter()
+        .map(|link| AgentLinkData {
+            agent_a: link.agent_a.clone(),
+            signature_a: link.signature_a.clone(),
+            agent_b: link.agent_b.clone(),
+            signature_b: link.signature_b.clone(),
+            created_at: link.created_at,
+            delegation: link.delegation.clone().map(|signature| LinkDelegationData {
+                signer: export.agent.clone(),
+                signature,
+            }),
+        })
+        .collect();
+    let links: LinksImportData = call_zome(
+        "agent_linking",
+        "import_links",
+        ImportLinksRequest {
+            signer: export.agent.clone(),
+            links,
+        },
+    )?;
+
+    let provenance = MigrationProvenance {
+        export: signed,
+        profile: profile_record.action_address().clone(),
+        imported_at: sys_time()?.as_seconds_and_nanos().0,
+    };
+    let provenance_hash = create_entry(&EntryZomes::IntegrityUsers(
+        EntryTypes::MigrationProvenance(provenance),
+    ))?;
+
+    create_link(
+        my_agent_pub_key,
+        provenance_hash,
+        LinkTypes::AgentToMigrations,
+        (),
+    )?;
+
+    Ok(ImportReport {
+        profile: profile_record,
+        skipped_memberships: memberships.skipped,
+        skipped_links: links.skipped,
+    })
+}
+
+/// Where the migration from a previous DNA role stands
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MigrationStatus {
+    /// None for a role that predates export_identity, which doesn't report its DNA
+    pub previous_dna_hash: Option<DnaHash>,
+    /// A MigrationProvenance for the previous DNA exists
+    pub completed: bool,
+    /// A matching profile exists without its provenance (e.g. an import
+    /// made before provenance was recorded); migrating again completes it
+    pub interrupted: bool,
+    pub provenance: Option<ActionHash>,
+}
+
+/// Start of the host error for calling a function the target zome doesn't
+/// have (the ribosome's ZomeFnNotExists)
+const MISSING_ZOME_FUNCTION_ERROR: &str = "Attempted to call a zome function that doesn't exist";
+
+/// Call a zome function on another role in this hApp (a previous DNA version),
+/// or None if that role's zome has no such function. Every other failure
+/// is CALL_FAILED.
+fn try_call_role<I, O>(
+    role_name: &str,
+    zome_name: &str,
+    fn_name: &str,
+    payload: I,
+) -> ExternResult<Option<O>>
+where
+    I: Serialize + std::fmt::Debug,
+    O: serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let call_failed = |reason: String| {
+        wasm_error!(IdentityError::CallFailed(format!(
+            "Call to {}.{} on role '{}' failed: {}",
+            zome_name, fn_name, role_name, reason
+        )))
+    };
+
+    let response = match call(
+        CallTargetCell::OtherRole(role_name.into()),
+        ZomeName::from(zome_name),
+        FunctionName::from(fn_name),
+        None,
+        payload,
+    ) {
+        Ok(response) => response,
+        Err(e) => {
+            return match &e.error {
+                WasmErrorInner::Host(message) if message.contains(MISSING_ZOME_FUNCTION_ERROR) => {
+                    Ok(None)
+                }
+                _ => Err(call_failed(format!("{:?}", e))),
+            }
+        }
+    };
+
+    match response {
+        ZomeCallResponse::Ok(output) => output
+            .decode()
+            .map(Some)
+            .map_err(|e| call_failed(format!("unexpected response: {}", e))),
+        other => Err(call_failed(format!("{:?}", other))),
+    }
+}
+
+/// Call a zome function on another role in this hApp that must exist there
+fn call_role<I, O>(role_name: &str, zome_name: &str, fn_name: &str, payload: I) -> ExternResult<O>
+where
+    I: Serialize + std::fmt::Debug,
+    O: serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    try_call_role(role_name, zome_name, fn_name, payload)?.ok_or_else(|| {
+        wasm_error!(IdentityError::CallFailed(format!(
+            "Role '{}' has no {}.{}",
+            role_name, zome_name, fn_name
+        )))
+    })
+}
+
+/// Pull a signed export from another role in this hApp (a previous DNA
+/// version), or None if the role predates export_identity
+fn export_from_role(role_name: &str) -> ExternResult<Option<SignedIdentityExport>> {
+    try_call_role(
+        role_name,
+        "users",
+        "export_identity",
+        Some(agent_info()?.agent_initial_pubkey),
+    )
+}
+
+/// The profile a role that predates export_identity (v1.0-v1.4) holds, as
+/// it imports here (PII dropped). Read with export_all_data, or from the
+/// profile record on v1.0, which has no export_all_data.
+fn profile_from_legacy_role(role_name: &str) -> ExternResult<LatestUserProfile> {
+    if let Some(profile) = try_call_role(role_name, "users", "export_all_data", ())? {
+        return legacy_profile(profile);
+    }
+
+    let record: Option<Record> = call_role(role_name, "users", "get_my_profile", ())?;
+    let profile: LegacyUserProfile = record
+        .ok_or(wasm_error!(IdentityError::ProfileNotFound(format!(
+            "No profile found on role '{}'",
+            role_name
+        ))))?
+        .entry()
+        .to_app_option()
+        .map_err(|_| wasm_error!(IdentityError::WrongEntryType(
+            "Could not deserialize the legacy profile".into()
+        )))?
+        .ok_or(wasm_error!(IdentityError::WrongEntryType("Profile entry is None".into())))?;
+    legacy_profile(profile)
+}
+
+/// Migrate from a role that predates export_identity. Nothing there is
+/// signed, so no MigrationProvenance is recorded: the profile is imported
+/// as import_legacy_profile does, and the role's site memberships are
+/// re-joined (see sites.import_memberships). Agent links can't be carried,
+/// since those versions don't return their signatures; link again with
+/// create_direct_link. All of it is committed together or not at all.
+fn import_from_legacy_role(role_name: &str) -> ExternResult<ImportReport> {
+    let profile = profile_from_legacy_role(role_name)?;
+
+    let legacy_memberships: Vec<Record> = call_role(role_name, "sites", "get_my_sites", ())?;
+    let site_ids: Vec<String> = legacy_memberships
+        .iter()
+        .filter_map(|record| record.entry().to_app_option::<MembershipData>().ok().flatten())
+        .map(|membership| membership.site_id)
+        .collect();
+
+    // Migrating again keeps the profile if it carries the same DID
+    let profile_record = match get_my_profile(())? {
+        Some(existing) => {
+            let existing_profile: Option<UserProfile> = existing.entry().to_app_option().ok().flatten();
+            if existing_profile.and_then(|existing| existing.did()).as_ref() != Some(&profile.did) {
+                return Err(wasm_error!(IdentityError::ProfileExists(
+                    "This agent already has a different profile".into()
+                )));
+            }
+            existing
+        }
+        None => register_user(profile)?,
+    };
+
+    let memberships: MembershipsImportData = call_zome("sites", "import_memberships", site_ids)?;
+
+    Ok(ImportReport {
+        profile: profile_record,
+        skipped_memberships: memberships.skipped,
+        skipped_links: Vec::new(),
+    })
+}
+
+/// Migrate from a previous DNA version installed in the same hApp under `role_name`.
+/// Pulls the signed export over a cross-cell call, verifies it and imports it
+/// (see import_data). Roles without export_identity (v1.1-v1.4) fall back
+/// to their export_all_data and get_my_sites; any other failure to reach
+/// the role is CALL_FAILED.
+/// Safe to call again: a finished migration returns the imported profile.
+#[hdk_extern]
+pub fn migrate_from_previous(role_name: String) -> ExternResult<ImportReport> {
+    match export_from_role(&role_name)? {
+        Some(signed) => import_signed_export(signed),
+        None => import_from_legacy_role(&role_name),
+    }
+}
+
+/// Check whether this agent has migrated from the previous DNA version in `role_name`
+#[hdk_extern]
+pub fn get_migration_status(role_name: String) -> ExternResult<MigrationStatus> {
+    let Some(signed) = export_from_role(&role_name)? else {
+        // A legacy role leaves no provenance: done once the profile is here
+        let profile = profile_from_legacy_role(&role_name)?;
+        let existing_profile: Option<UserProfile> = get_my_profile(())?
+            .and_then(|existing| existing.entry().to_app_option().ok().flatten());
+        return Ok(MigrationStatus {
+            previous_dna_hash: None,
+            completed: existing_profile.and_then(|existing| existing.did()) == Some(profile.did),
+            interrupted: false,
+            provenance: None,
+        });
+    };
+    let export = signed.export()?;
+    let previous_dna_hash = export.dna_hash.clone();
+
+    let provenance = my_migrations()?
+        .into_iter()
+        .find(|(_, provenance)| {
+            provenance
+                .export
+                .export()
+                .is_ok_and(|imported| imported.dna_hash == previous_dna_hash)
+        })
+        .map(|(record, _)| record.action_address().clone());
+
+    let interrupted = match (&provenance, get_my_profile(())?) {
+        (None, Some(existing)) => {
+            let existing_profile: Option<UserProfile> = existing.entry().to_app_option().ok().flatten();
+            existing_profile.as_ref()
+                == export.profile_history.last().map(|exported| &exported.profile)
+        }
+        _ => false,
+    };
+
+    Ok(MigrationStatus {
+        previous_dna_hash: Some(previous_dna_hash),
+        completed: provenance.is_some(),
+        interrupted,
+        provenance,
+    })
+}
+
+/// The migration steps behind a set of MigrationProvenance records made on
+/// this DNA, oldest first. Each hop comes from a signed export that
+/// validation checked: the one imported here, then the exports nested in its
+/// lineage for the hops before it. Hops are ordered by source chain and
+/// lineage order, never by self-reported times.
+fn lineage_of(mut migrations: Vec<(Record, MigrationProvenance)>) -> ExternResult<Vec<MigrationStep>> {
+    migrations.sort_by_key(|(record, _)| record.action().action_seq());
+    let dna_hash = dna_info()?.hash;
+
+    let mut steps: Vec<MigrationStep> = Vec::new();
+    for (record, provenance) in migrations {
+        push_migration_steps(
+            &mut steps,
+            &provenance.export,
+            &dna_hash,
+            record.action().author(),
+            Some(record.action_address().clone()),
+        )?;
+    }
+
+    Ok(steps)
+}
+
+/// Add the hops behind a signed export (oldest first), then the hop it made
+/// into `to_dna_hash`
+fn push_migration_steps(
+    steps: &mut Vec<MigrationStep>,
+    signed: &SignedIdentityExport,
+    to_dna_hash: &DnaHash,
+    to_agent: &AgentPubKey,
+    provenance: Option<ActionHash>,
+) -> ExternResult<()> {
+    let export = signed.export()?;
+    for earlier in &export.lineage {
+        push_migration_steps(steps, earlier, &export.dna_hash, &export.agent, None)?;
+    }
+
+    let step = MigrationStep {
+        from_dna_hash: export.dna_hash,
+        from_agent: export.agent,
+        to_dna_hash: to_dna_hash.clone(),
+        to_agent: to_agent.clone(),
+        provenance,
+        exported_at: export.exported_at,
+    };
+    if !steps.contains(&step) {
+        steps.push(step);
+    }
+
+    Ok(())
+}
+
+/// Get the migration history of an agent's identity, oldest first: which DNA
+/// hashes and agent keys it came from, and when. Lets a site that stored an
+/// agent key from an earlier version match it to the agent's current profile.
+#[hdk_extern]
+pub fn get_migration_lineage(agent: AgentPubKey) -> ExternResult<Vec<MigrationStep>> {
+    let links = get_links(
+        LinkQuery::try_new(agent.clone(), LinkTypes::AgentToMigrations)?,
+        GetStrategy::default()
+    )?;
+
+    let mut migrations = Vec::new();
+    for link in links {
+        let Some(provenance_hash) = link.target.into_action_hash() else {
+            continue;
+        };
+        let Some(record) = get(provenance_hash, GetOptions::default())? else {
+            continue;
+        };
+        if record.action().author() != &agent {
+            continue;
+        }
+        if let Ok(Some(provenance)) = record.entry().to_app_option::<MigrationProvenance>() {
+            migrations.push((record, provenance));
+        }
+    }
+
+    lineage_of(migrations)
+}
+
+const CREDENTIALS_V2_CONTEXT: &str = "https://www.w3.org/ns/credentials/v2";
+const DID_V1_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+const MULTIKEY_V1_CONTEXT: &str = "https://w3id.org/security/multikey/v1";
+
+/// Seconds since the epoch as an XML Schema dateTime in UTC (`2026-01-31T12:00:00Z`)
+fn xsd_datetime(seconds: i64) -> String {
+    let days = seconds.div_euclid(86_400);
+    let second_of_day = seconds.rem_euclid(86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's days_from_civil inverse)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3_600,
+        second_of_day % 3_600 / 60,
+        second_of_day % 60
+    )
+}
+
+/// JCS (RFC 8785) canonical form of a JSON document: no whitespace, object
+/// keys sorted by UTF-16 code units, strings escaped as serde_json's compact
+/// writer does (the same escapes JCS uses). Keys are sorted here rather than
+/// left to serde_json's map, whose order the `preserve_order` feature
+/// changes. Numbers must be integers a double holds exactly (JCS writes
+/// numbers the ECMAScript way, which isn't implemented for the rest); the
+/// documents this zome signs have no others.
+fn jcs_bytes(document: &serde_json::Value) -> ExternResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_jcs(document, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn write_jcs(value: &serde_json::Value, out: &mut Vec<u8>) -> ExternResult<()> {
+    const MAX_EXACT_INTEGER: u64 = 1 << 53;
+
+    match value {
+        serde_json::Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_jcs(item, out)?;
+            }
+            out.push(b']');
+        }
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push(b'{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_json(key, out)?;
+                out.push(b':');
+                write_jcs(item, out)?;
+            }
+            out.push(b'}');
+        }
+        serde_json::Value::Number(number)
+            if !number
+                .as_i64()
+                .map(i64::unsigned_abs)
+                .or(number.as_u64())
+                .is_some_and(|magnitude| magnitude <= MAX_EXACT_INTEGER) =>
+        {
+            return Err(wasm_error!(IdentityError::Serialization(format!(
+                "JCS: unsupported number {}",
+                number
+            ))));
+        }
+        scalar => write_json(scalar, out)?,
+    }
+    Ok(())
+}
+
+/// Compact JSON of a string, number, boolean or null
+fn write_json<T: Serialize + ?Sized>(value: &T, out: &mut Vec<u8>) -> ExternResult<()> {
+    serde_json::to_writer(out, value)
+        .map_err(|e| wasm_error!(IdentityError::Serialization(e.to_string())))
+}
+
+/// What an `eddsa-jcs-2022` proof signs: SHA-256 of the JCS proof
+/// configuration followed by SHA-256 of the JCS document (without its proof)
+fn eddsa_jcs_2022_hash_data(
+    proof_config: &serde_json::Value,
+    document: &serde_json::Value,
+) -> ExternResult<Vec<u8>> {
+    let mut hash_data = Sha256::digest(jcs_bytes(proof_config)?).to_vec();
+    hash_data.extend(Sha256::digest(jcs_bytes(document)?));
+    Ok(hash_data)
+}
+
+/// Attach an `eddsa-jcs-2022` Data Integrity proof, signed with the agent's key.
+///
+/// The signed bytes are SHA-256(JCS(proof options + document @context)) followed
+/// by SHA-256(JCS(document)), signed raw (no msgpack wrapping) so any W3C
+/// verifier can check it against the did:key verification method.
+fn add_data_integrity_proof(
+    mut document: serde_json::Value,
+    agent: &AgentPubKey,
+    proof_purpose: &str,
+    created: &str,
+) -> ExternResult<serde_json::Value> {
+    let holder = did_key(agent);
+    let mut proof = serde_json::json!({
+        "type": "DataIntegrityProof",
+        "cryptosuite": "eddsa-jcs-2022",
+        "created": created,
+        "verificationMethod": format!("{}#{}", holder, holder.trim_start_matches("did:key:")),
+        "proofPurpose": proof_purpose,
+    });
+
+    let mut proof_config = proof.clone();
+    proof_config["@context"] = document["@context"].clone();
+
+    let hash_data = eddsa_jcs_2022_hash_data(&proof_config, &document)?;
+    let signature = sign_raw(agent.clone(), hash_data)?;
+
+    proof["proofValue"] = serde_json::Value::String(format!("z{}", base58btc(&signature.0)));
+    document["proof"] = proof;
+    Ok(document)
+}
+
+/// A self-issued credential about this identity's DID
+fn self_issued_credential(
+    credential_type: &str,
+    holder: &str,
+    valid_from: &str,
+    subject: serde_json::Value,
+) -> serde_json::Value {
+    serde_json::json!({
+        "@context": [CREDENTIALS_V2_CONTEXT],
+        "type": ["VerifiableCredential", credential_type],
+        "issuer": holder,
+        "validFrom": valid_from,
+        "credentialSubject": subject,
+    })
+}
+
+/// Export this identity as a W3C Verifiable Presentation (JSON-LD, returned as
+/// a JSON string) for use outside Holochain.
+///
+/// The presentation carries the DID Document and one self-issued Verifiable
+/// Credential each for the profile, every non-revoked agent link and every
+/// active site membership. The credentials and the presentation are each
+/// secured with an `eddsa-jcs-2022` proof by the agent's key, whose
+/// verification method is the agent's did:key. Agent-link credentials also
+/// carry both agents' original Holochain signatures (multibase base58btc)
+/// over `sorted_agent_pair_bytes`.
+#[hdk_extern]
+pub fn export_verifiable_presentation(_: ()) -> ExternResult<String> {
+    let export = collect_identity_export(None)?;
+    let agent = export.agent.clone();
+    let profile = read_profile(
+        &export
+            .profile_history
+            .last()
+            .ok_or(wasm_error!(IdentityError::ProfileNotFound("No profile found to export".into())))?
+            .profile,
+    )?;
+    let holder = did_key(&agent);
+    let created = xsd_datetime(export.exported_at);
+
+    let verification_method = format!("{}#agent", profile.did);
+    let did_document = serde_json::json!({
+        "@context": [DID_V1_CONTEXT, MULTIKEY_V1_CONTEXT],
+        "id": profile.did,
+        "alsoKnownAs": [holder],
+        "verificationMethod": [{
+            "id": verification_method,
+            "type": "Multikey",
+            "controller": profile.did,
+            "publicKeyMultibase": holder.trim_start_matches("did:key:"),
+        }],
+        "authentication": [verification_method],
+        "assertionMethod": [verification_method],
+    });
+
+    let mut credentials = Vec::new();
+
+    let profile_credential = self_issued_credential(
+        "FlowstaProfileCredential",
+        &holder,
+        &created,
+        serde_json::json!({
+            "id": profile.did,
+            // Client-supplied timestamps, passed through as stored
+            "createdAt": profile.created_at,
+            "updatedAt": profile.updated_at,
+        }),
+    );
+    credentials.push(add_data_integrity_proof(profile_credential, &agent, "assertionMethod", &created)?);
+
+    for link in &export.agent_links {
+        let link_credential = self_issued_credential(
+            "FlowstaAgentLinkCredential",
+            &holder,
+            &created,
+            serde_json::json!({
+                "id": profile.did,
+                "sameAs": [did_key(&link.agent_a), did_key(&link.agent_b)],
+                "holochainAttestation": {
+                    "agentA": link.agent_a.to_string(),
+                    "signatureA": format!("z{}", base58btc(&link.signature_a.0)),
+                    "agentB": link.agent_b.to_string(),
+                    "signatureB": format!("z{}", base58btc(&link.signature_b.0)),
+                    "linkedAt": xsd_datetime(link.created_at),
+                },
+            }),
+        );
+        credentials.push(add_data_integrity_proof(link_credential, &agent, "assertionMethod", &created)?);
+    }
+
+    for membership in &export.memberships {
+        let mut subject = serde_json::json!({
+            "id": profile.did,
+            "memberOf": membership.site_id,
+            "joinedAt": xsd_datetime(membership.joined_at),
+        });
+        if let Some(expires_at) = membership.expires_at {
+            subject["expiresAt"] = serde_json::Value::String(xsd_datetime(expires_at));
+        }
+        let membership_credential =
+            self_issued_credential("FlowstaSiteMembershipCredential", &holder, &created, subject);
+        credentials.push(add_data_integrity_proof(membership_credential, &agent, "assertionMethod", &created)?);
+    }
+
+    let presentation = serde_json::json!({
+        "@context": [CREDENTIALS_V2_CONTEXT],
+        "type": ["VerifiablePresentation"],
+        "holder": holder,
+        "didDocument": did_document,
+        "verifiableCredential": credentials,
+    });
+    let presentation = add_data_integrity_proof(presentation, &agent, "authentication", &created)?;
+
+    serde_json::to_string(&presentation)
+        .map_err(|e| wasm_error!(IdentityError::Serialization(e.to_string())))
+}
+
+/// Emit a signal for each committed action that changes the profile
+#[hdk_extern(infallible)]
+pub fn post_commit(committed_actions: Vec<SignedActionHashed>) {
+    for action in committed_actions {
+        if let Err(err) = signal_action(action) {
+            error!("Error signaling new action: {:?}", err);
+        }
+    }
+}
+
+fn signal_action(action: SignedActionHashed) -> ExternResult<()> {
+    // Creates and updates carry their entry type, so only profiles are looked up
+    let user_profile: EntryType = UnitEntryTypes::UserProfile.try_into()?;
+    if action.action().entry_type() != Some(&user_profile) {
+        return Ok(());
+    }
+    let action_hash = action.as_hash().clone();
+    if let Some(EntryTypes::UserProfile(profile)) = committed_entry(&action_hash)? {
+        emit_signal(UsersSignal::ProfileUpdated {
+            action_hash,
+            profile: read_profile(&profile)?,
+        })?;
+    }
+    Ok(())
+}
+
+/// The users entry an action created, if any
+fn committed_entry(action_hash: &ActionHash) -> ExternResult<Option<EntryTypes>> {
+    let Some(record) = get(action_hash.clone(), GetOptions::default())? else {
+        return Ok(None);
+    };
+    let Some(EntryType::App(AppEntryDef { zome_index, entry_index, .. })) = record.action().entry_type() else {
+        return Ok(None);
+    };
+    let Some(entry) = record.entry().as_option() else {
+        return Ok(None);
+    };
+    EntryTypes::deserialize_from_type(*zome_index, *entry_index, entry)
+}
+
+#[cfg(test)]
+mod tests {
+    //! eddsa-jcs-2022 known answers: the JCS example credential from the W3C
+    //! Data Integrity EdDSA Cryptosuites spec (vc-di-eddsa, "Representation:
+    //! eddsa-jcs-2022"), with the spec's test key pair and proof options.
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    /// The spec's secretKeyMultibase (z3u2en7t5LR2WtQH5PfFqMqwVHBeXouLzo6haApm8XHqvjxq)
+    /// without its base58btc and ed25519-priv multicodec wrapping
+    const SECRET_KEY_SEED: &str = "c96ef9ea10c5e414c471723aff9de72c35fa5b70fae97e8832ecac7d2e2b8ed6";
+    const PUBLIC_KEY_MULTIBASE: &str = "z6MkrJVnaZkeFzdQyMZu1cgjg7k1pZZ6pvBQ7XJPt4swbTQ2";
+    const CANONICAL_CREDENTIAL: &str = concat!(
+        r#"{"@context":["https://www.w3.org/ns/credentials/v2","https://www.w3.org/ns/credentials/examples/v2"],"#,
+        r#""credentialSubject":{"alumniOf":"The School of Examples","id":"did:example:abcdefgh"},"#,
+        r#""description":"A minimum viable example of an Alumni Credential.","#,
+        r#""id":"urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33","issuer":"https://vc.example/issuers/5678","#,
+        r#""name":"Alumni Credential","type":["VerifiableCredential","AlumniCredential"],"#,
+        r#""validFrom":"2023-01-01T00:00:00Z"}"#,
+    );
+    const CANONICAL_PROOF_CONFIG: &str = concat!(
+        r#"{"@context":["https://www.w3.org/ns/credentials/v2","https://www.w3.org/ns/credentials/examples/v2"],"#,
+        r#""created":"2023-02-24T23:36:38Z","cryptosuite":"eddsa-jcs-2022","proofPurpose":"assertionMethod","#,
+        r#""type":"DataIntegrityProof","#,
+        r#""verificationMethod":"did:key:z6MkrJVnaZkeFzdQyMZu1cgjg7k1pZZ6pvBQ7XJPt4swbTQ2#z6MkrJVnaZkeFzdQyMZu1cgjg7k1pZZ6pvBQ7XJPt4swbTQ2"}"#,
+    );
+    /// SHA-256 of the canonical proof configuration, then of the canonical credential
+    const HASH_DATA: &str = concat!(
+        "66ab154f5c2890a140cb8388a22a160454f80575f6eae09e5a097cabe539a1db",
+        "59b7cb6251b8991add1ce0bc83107e3db9dbbab5bd2c28f687db1a03abc92f19",
+    );
+    const PROOF_VALUE: &str =
+        "z2HnFSSPPBzR36zdDgK8PbEHeXbR56YF24jwMpt3R1eHXQzJDMWS93FCzpvJpwTWd3GAVFuUfjoJdcnTMuVor51aX";
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// The spec's unsecured credential, keys in the spec's order
+    fn credential() -> serde_json::Value {
+        serde_json::json!({
+            "@context": [CREDENTIALS_V2_CONTEXT, "https://www.w3.org/ns/credentials/examples/v2"],
+            "id": "urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33",
+            "type": ["VerifiableCredential", "AlumniCredential"],
+            "name": "Alumni Credential",
+            "description": "A minimum viable example of an Alumni Credential.",
+            "issuer": "https://vc.example/issuers/5678",
+            "validFrom": "2023-01-01T00:00:00Z",
+            "credentialSubject": {
+                "id": "did:example:abcdefgh",
+                "alumniOf": "The School of Examples",
+            },
+        })
+    }
+
+    /// The spec's proof options with the credential's @context, as add_data_integrity_proof builds them
+    fn proof_config() -> serde_json::Value {
+        serde_json::json!({
+            "type": "DataIntegrityProof",
+            "cryptosuite": "eddsa-jcs-2022",
+            "created": "2023-02-24T23:36:38Z",
+            "verificationMethod": format!("did:key:{0}#{0}", PUBLIC_KEY_MULTIBASE),
+            "proofPurpose": "assertionMethod",
+            "@context": credential()["@context"].clone(),
+        })
+    }
+
+    #[test]
+    fn canonicalizes_the_w3c_example() {
+        assert_eq!(jcs_bytes(&credential()).unwrap(), CANONICAL_CREDENTIAL.as_bytes());
+        assert_eq!(jcs_bytes(&proof_config()).unwrap(), CANONICAL_PROOF_CONFIG.as_bytes());
+    }
+
+    #[test]
+    fn proves_the_w3c_example() {
+        let hash_data = eddsa_jcs_2022_hash_data(&proof_config(), &credential()).unwrap();
+        assert_eq!(hash_data, bytes(HASH_DATA));
+
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&bytes(SECRET_KEY_SEED));
+        let key = SigningKey::from_bytes(&seed);
+        let proof_value = format!("z{}", base58btc(&key.sign(&hash_data).to_bytes()));
+        assert_eq!(proof_value, PROOF_VALUE);
+
+        // The verification method add_data_integrity_proof names for this key
+        let agent = AgentPubKey::from_raw_32(key.verifying_key().to_bytes().to_vec());
+        assert_eq!(did_key(&agent), format!("did:key:{}", PUBLIC_KEY_MULTIBASE));
+    }
+
+    #[test]
+    fn sorts_keys_by_utf16_code_units() {
+        // RFC 8785 section 3.2.3: U+1F600 (a surrogate pair) sorts before U+FB33
+        let document = serde_json::json!({
+            "\u{20ac}": "Euro Sign",
+            "\r": "Carriage Return",
+            "\u{fb33}": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\u{1f600}": "Emoji: Grinning Face",
+            "\u{80}": "Control",
+            "\u{f6}": "Latin Small Letter O With Diaeresis",
+        });
+        let expected = concat!(
+            "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",",
+            "\"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",",
+            "\"\u{1f600}\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}",
+        );
+        assert_eq!(jcs_bytes(&document).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn rejects_numbers_it_cannot_write_exactly() {
+        assert!(jcs_bytes(&serde_json::json!({ "n": 1.5 })).is_err());
+        assert!(jcs_bytes(&serde_json::json!({ "n": (1u64 << 53) + 1 })).is_err());
+        assert_eq!(
+            jcs_bytes(&serde_json::json!({ "n": -(1i64 << 53) })).unwrap(),
+            br#"{"n":-9007199254740992}"#,
+        );
+    }
+}
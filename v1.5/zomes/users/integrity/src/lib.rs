@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+
+use hdi::prelude::*;
+use serde::de::IgnoredAny;
+
+/// Field names that held PII in v1.0-v1.3 profiles. No profile schema may
+/// use them, so validation rejects any payload that carries one.
+pub const LEGACY_PII_FIELDS: [&str; 4] = [
+    "email_hash",         // 🔴 REMOVED in v1.2 (brute-forceable PII vulnerability)
+    "display_name",       // 🔴 REMOVED in v1.2 (contains email username - PII)
+    "profile_picture",    // 🔴 REMOVED in v1.4 (identifiable - moved to private DNA)
+    "has_custom_picture", // 🔴 REMOVED in v1.4 (moved to private DNA)
+];
+
+/// A stored profile: one schema version's fields as a msgpack map, tagged
+/// with that version. The shapes are defined outside this zome (the
+/// users_profile crate) and decoded by the users coordinator, so a new
+/// schema version doesn't change this entry or the DNA hash. Validation
+/// only holds every version to a DID and no legacy PII fields.
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct UserProfile {
+    /// Schema version `payload` is written in (1 and up)
+    pub schema: u32,
+    /// msgpack map of the profile fields, keyed by field name
+    pub payload: Vec<u8>,
+}
+
+/// The one field every profile schema version carries
+#[derive(Deserialize)]
+struct ProfileDid {
+    did: String,
+}
+
+impl UserProfile {
+    /// The payload's DID, if it is a map with a string `did`
+    pub fn did(&self) -> Option<String> {
+        holochain_serialized_bytes::decode::<_, ProfileDid>(&self.payload)
+            .ok()
+            .map(|profile| profile.did)
+    }
+}
+
+/// Version of the IdentityExport format this DNA produces
+pub const IDENTITY_EXPORT_VERSION: u32 = 1;
+
+/// A UserProfile record as it was on the exporting DNA
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedProfile {
+    /// Original action hash (create or update) on the exporting DNA
+    pub action_hash: ActionHash,
+    pub profile: UserProfile,
+}
+
+/// An active public SiteMembership as it was on the exporting DNA
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedMembership {
+    /// Original action hash on the exporting DNA
+    pub action_hash: ActionHash,
+    pub site_id: String,
+    pub joined_at: i64,
+    /// SiteInvite it redeemed (an action hash on the exporting DNA)
+    pub invite: Option<ActionHash>,
+    pub expires_at: Option<i64>,
+}
+
+/// A non-revoked IsSamePersonEntry, with both signatures over the sorted key pair
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedAgentLink {
+    /// Original action hash on the exporting DNA
+    pub action_hash: ActionHash,
+    pub agent_a: AgentPubKey,
+    pub signature_a: Signature,
+    pub agent_b: AgentPubKey,
+    pub signature_b: Signature,
+    pub created_at: i64,
+    /// When the export names a recipient: the exporting agent's delegation
+    /// of this link to it (agent_linking's `link_delegation_payload`), so
+    /// the recipient can publish the link on the next version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<Signature>,
+}
+
+/// One hop of an identity's migration history, backed by the signed export
+/// it imported (see `IdentityExport::lineage`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MigrationStep {
+    /// DNA and agent the identity was exported from
+    pub from_dna_hash: DnaHash,
+    pub from_agent: AgentPubKey,
+    /// DNA and agent that imported it
+    pub to_dna_hash: DnaHash,
+    pub to_agent: AgentPubKey,
+    /// The MigrationProvenance recording the hop, for hops into this DNA
+    pub provenance: Option<ActionHash>,
+    /// When the exporting agent made the export, as it signed it
+    pub exported_at: i64,
+}
+
+/// Everything an identity holds in one DNA version, for import into the next.
+///
+/// Covers all three zomes: the profile with its full update history, active
+/// public site memberships, and non-revoked agent links. Pairwise
+/// memberships are private to the source chain and are not exported.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct IdentityExport {
+    /// `IDENTITY_EXPORT_VERSION` of the exporting DNA
+    pub version: u32,
+    /// The exporting agent
+    pub agent: AgentPubKey,
+    /// Agent allowed to import the bundle, when it differs from `agent`
+    /// (a new key on the next DNA version)
+    #[serde(default)]
+    pub recipient: Option<AgentPubKey>,
+    /// DNA the data was exported from
+    pub dna_hash: DnaHash,
+    pub exported_at: i64,
+    /// Profile create and updates, oldest first (the last one is current)
+    pub profile_history: Vec<ExportedProfile>,
+    pub memberships: Vec<ExportedMembership>,
+    pub agent_links: Vec<ExportedAgentLink>,
+    /// The signed exports this identity was imported from on the exporting
+    /// DNA, oldest first. Each carries its own lineage, so every earlier hop
+    /// can be verified on the next version (see `check_signed_export`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lineage: Vec<SignedIdentityExport>,
+}
+
+/// An IdentityExport exactly as the exporting agent serialized and signed it.
+///
+/// The signature covers `export_bytes` rather than a re-encoding of the
+/// struct, so a later DNA version whose IdentityExport has a different shape
+/// still verifies it over the same bytes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct SignedIdentityExport {
+    /// msgpack encoding of the IdentityExport
+    pub export_bytes: Vec<u8>,
+    /// The exporting agent's signature over `identity_export_signing_bytes(&export_bytes)`
+    pub signature: Signature,
+}
+
+impl SignedIdentityExport {
+    /// Serialize an export into the bytes that get signed and shipped
+    pub fn encode_export(export: &IdentityExport) -> ExternResult<Vec<u8>> {
+        let encoded = SerializedBytes::try_from(export.clone())
+            .map_err(|e| wasm_error!(WasmErrorInner::Serialize(e)))?;
+        Ok(encoded.bytes().to_vec())
+    }
+
+    /// Decode the signed bytes. Doesn't check the signature (see `check_signed_export`).
+    pub fn export(&self) -> ExternResult<IdentityExport> {
+        IdentityExport::try_from(SerializedBytes::from(UnsafeBytes::from(
+            self.export_bytes.clone(),
+        )))
+        .map_err(|e| wasm_error!(WasmErrorInner::Serialize(e)))
+    }
+}
+
+/// Bytes the exporting agent signs: a domain prefix followed by the
+/// serialized export, as shipped in `SignedIdentityExport::export_bytes`
+pub fn identity_export_signing_bytes(export_bytes: &[u8]) -> Vec<u8> {
+    let mut bytes = b"flowsta-identity-export-v1:".to_vec();
+    bytes.extend_from_slice(export_bytes);
+    bytes
+}
+
+/// Records where an imported profile came from.
+///
+/// Carries the whole signed export, so anyone can check that the old
+/// agent on the old DNA produced it and that `profile` is what it held.
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq)]
+pub struct MigrationProvenance {
+    pub export: SignedIdentityExport,
+    /// The UserProfile the import created on this DNA
+    pub profile: ActionHash,
+    pub imported_at: i64,
+}
+
+#[hdk_entry_types]
+#[unit_enum(UnitEntryTypes)]
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum EntryTypes {
+    UserProfile(UserProfile),
+    MigrationProvenance(MigrationProvenance),
+}
+
+#[derive(Serialize, Deserialize)]
+#[hdk_link_types]
+pub enum LinkTypes {
+    AgentToProfile,
+    /// Links from the importing agent to its MigrationProvenance entries
+    AgentToMigrations,
+}
+
+#[cfg_attr(not(feature = "integrity"), hdk_extern)]
+pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
+    // An entry that doesn't deserialize is invalid, not an error
+    let flat_op = match op.flattened::<EntryTypes, LinkTypes>() {
+        Ok(flat_op) => flat_op,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Entry does not match any known entry type: {:?}",
+                e
+            )))
+        }
+    };
+
+    match flat_op {
+        FlatOp::StoreEntry(store_entry) => match store_entry {
+            OpEntry::CreateEntry {
+                app_entry: EntryTypes::MigrationProvenance(provenance),
+                action,
+            } => validate_migration_provenance(&provenance, &action.author),
+            OpEntry::CreateEntry {
+                app_entry: EntryTypes::UserProfile(profile),
+                ..
+            }
+            | OpEntry::UpdateEntry {
+                app_entry: EntryTypes::UserProfile(profile),
+                ..
+            } => Ok(validate_user_profile(&profile)),
+            OpEntry::UpdateEntry {
+                app_entry: EntryTypes::MigrationProvenance(_),
+                ..
+            } => Ok(ValidateCallbackResult::Invalid(
+                "Migration provenance cannot be updated".to_string(),
+            )),
+            _ => Ok(ValidateCallbackResult::Valid),
+        },
+        FlatOp::RegisterCreateLink {
+            link_type: LinkTypes::AgentToMigrations,
+            base_address,
+            target_address,
+            action,
+            ..
+        } => {
+            if base_address != AnyLinkableHash::from(action.author.clone()) {
+                return Ok(ValidateCallbackResult::Invalid(
+                    "Migrations can only be linked from the importing agent".to_string(),
+                ));
+            }
+            let provenance_hash = match target_address.into_action_hash() {
+                Some(hash) => hash,
+                None => {
+                    return Ok(ValidateCallbackResult::Invalid(
+                        "AgentToMigrations must target a MigrationProvenance action".to_string(),
+                    ))
+                }
+            };
+            let provenance_record = must_get_valid_record(provenance_hash)?;
+            let is_own_provenance = matches!(
+                provenance_record.entry().to_app_option::<MigrationProvenance>(),
+                Ok(Some(_))
+            ) && provenance_record.action().author() == &action.author;
+            if !is_own_provenance {
+                return Ok(ValidateCallbackResult::Invalid(
+                    "AgentToMigrations must target the agent's own MigrationProvenance".to_string(),
+                ));
+            }
+            Ok(ValidateCallbackResult::Valid)
+        }
+        _ => Ok(ValidateCallbackResult::Valid),
+    }
+}
+
+/// Validate a UserProfile. The payload's shape is the coordinator's
+/// business; every schema version must still have:
+/// 1. A schema version of 1 or more
+/// 2. A payload that is a msgpack map keyed by field name
+/// 3. None of the LEGACY_PII_FIELDS
+/// 4. A non-empty `did`
+fn validate_user_profile(profile: &UserProfile) -> ValidateCallbackResult {
+    if profile.schema == 0 {
+        return ValidateCallbackResult::Invalid("Profile schema versions start at 1".to_string());
+    }
+
+    let Ok(fields) = holochain_serialized_bytes::decode::<_, BTreeMap<String, IgnoredAny>>(&profile.payload)
+    else {
+        return ValidateCallbackResult::Invalid(
+            "Profile payload must be a msgpack map of named fields".to_string(),
+        );
+    };
+
+    if let Some(field) = LEGACY_PII_FIELDS.iter().find(|field| fields.contains_key(**field)) {
+        return ValidateCallbackResult::Invalid(format!(
+            "Profile carries the legacy PII field {}",
+            field
+        ));
+    }
+
+    if profile.did().unwrap_or_default().is_empty() {
+        return ValidateCallbackResult::Invalid("A profile needs a DID".to_string());
+    }
+
+    ValidateCallbackResult::Valid
+}
+
+/// Check a signed export: the exporting agent's signature over the shipped
+/// bytes (see `identity_export_signing_bytes`), a known version, some
+/// profile history and a valid lineage. Each export in the lineage is checked
+/// the same way, must come from another DNA, and must have been meant for
+/// the agent that exported the one carrying it, so the hops join up.
+/// Returns the decoded export, or the reason it can't be trusted.
+pub fn check_signed_export(
+    signed: &SignedIdentityExport,
+) -> ExternResult<Result<IdentityExport, String>> {
+    let Ok(export) = signed.export() else {
+        return Ok(Err("Export bytes do not decode as an IdentityExport".to_string()));
+    };
+
+    if !verify_signature(
+        export.agent.clone(),
+        signed.signature.clone(),
+        identity_export_signing_bytes(&signed.export_bytes),
+    )? {
+        return Ok(Err("Export signature does not verify against the exporting agent".to_string()));
+    }
+
+    if export.version == 0 || export.version > IDENTITY_EXPORT_VERSION {
+        return Ok(Err(format!("Unsupported export version {}", export.version)));
+    }
+
+    if export.profile_history.is_empty() {
+        return Ok(Err("Export has no profile".to_string()));
+    }
+
+    for signed_earlier in &export.lineage {
+        let earlier = match check_signed_export(signed_earlier)? {
+            Ok(earlier) => earlier,
+            Err(reason) => return Ok(Err(format!("Lineage export: {}", reason))),
+        };
+        if earlier.dna_hash == export.dna_hash {
+            return Ok(Err("Lineage export comes from the exporting DNA".to_string()));
+        }
+        if earlier.agent != export.agent && earlier.recipient.as_ref() != Some(&export.agent) {
+            return Ok(Err("Lineage export was not meant for the exporting agent".to_string()));
+        }
+    }
+
+    Ok(Ok(export))
+}
+
+/// Validate a MigrationProvenance:
+/// 1. The export and every export in its lineage must be signed by their
+///    exporting agents and join up (see `check_signed_export`)
+/// 2. It must come from another DNA
+/// 3. The author must be the exporting agent or the export's named recipient
+/// 4. profile must be a UserProfile by the author, equal to the export's current profile
+fn validate_migration_provenance(
+    provenance: &MigrationProvenance,
+    author: &AgentPubKey,
+) -> ExternResult<ValidateCallbackResult> {
+    let export = match check_signed_export(&provenance.export)? {
+        Ok(export) => export,
+        Err(reason) => return Ok(ValidateCallbackResult::Invalid(reason)),
+    };
+
+    if export.dna_hash == dna_info()?.hash {
+        return Ok(ValidateCallbackResult::Invalid(
+            "An export from this DNA is not a migration".to_string(),
+        ));
+    }
+
+    if &export.agent != author && export.recipient.as_ref() != Some(author) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the exporting agent or the export's recipient can import it".to_string(),
+        ));
+    }
+
+    let profile_record = must_get_valid_record(provenance.profile.clone())?;
+    let profile: UserProfile = match profile_record.entry().to_app_option() {
+        Ok(Some(profile)) => profile,
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "profile must reference a UserProfile".to_string(),
+            ))
+        }
+    };
+    if profile_record.action().author() != author {
+        return Ok(ValidateCallbackResult::Invalid(
+            "profile must be authored by the importing agent".to_string(),
+        ));
+    }
+    if export.profile_history.last().map(|exported| &exported.profile) != Some(&profile) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "profile does not match the exported profile".to_string(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
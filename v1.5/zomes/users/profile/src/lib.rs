@@ -0,0 +1,48 @@
+//! Profile schema versions, shared by the users coordinator and clients.
+//!
+//! The users integrity zome stores a profile as `UserProfile { schema,
+//! payload }`: a version number and the msgpack map of that version's
+//! fields. It only checks what every version must hold (a DID, no legacy PII
+//! field names). The shapes themselves live here and the coordinator decodes
+//! them by version, so adding a version changes this crate and the
+//! coordinator but not the integrity zome, the DNA hash or the network.
+//!
+//! Adding a version: define it, point `LatestUserProfile` and
+//! `LATEST_PROFILE_SCHEMA` at it, and give the coordinator's `read_profile`
+//! an arm that brings the previous latest forward. Never change a published
+//! version's struct; stored payloads keep its shape.
+
+use hdi::prelude::*;
+
+/// Schema 1: the v1.4 profile fields.
+/// Unknown fields are rejected, so no legacy PII can ride along in a profile.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+#[serde(deny_unknown_fields)]
+pub struct UserProfileV1 {
+    // 🔴 REMOVED in v1.2: email_hash (brute-forceable PII vulnerability)
+    // 🔴 REMOVED in v1.2: display_name (contains email username - PII)
+    // 🔴 REMOVED in v1.4: profile_picture (identifiable - moved to private DNA)
+    // 🔴 REMOVED in v1.4: has_custom_picture (moved to private DNA)
+
+    // ✅ SECURE: Only non-identifiable data on public DHT
+    pub did: String,                            // W3C DID (designed to be public)
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// The profile shape readers normalize to and new profiles are written in
+pub type LatestUserProfile = UserProfileV1;
+
+/// `UserProfile::schema` of `LatestUserProfile`
+pub const LATEST_PROFILE_SCHEMA: u32 = 1;
+
+/// Local signal from the users zome's post_commit, tagged by `type`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum UsersSignal {
+    /// A profile was registered, updated or imported
+    ProfileUpdated {
+        action_hash: ActionHash,
+        profile: LatestUserProfile,
+    },
+}